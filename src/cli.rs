@@ -13,7 +13,10 @@ pub fn get_cli() -> App<'static, 'static> {
                 .takes_value(true)
                 .value_name("NAME")
                 .help("Output format (defaults to hex)")
-                .possible_values(&["hex", "HEX", "hex!", "HEX!", "plain", "rgb"])
+                .possible_values(&[
+                    "hex", "HEX", "hex!", "HEX!", "plain", "rgb", "hsl", "hsv", "lab", "lch",
+                    "oklab", "oklch", "hexa", "rgba", "argb", "name",
+                ])
                 .conflicts_with("custom"),
         )
         .arg(
@@ -36,6 +39,21 @@ pub fn get_cli() -> App<'static, 'static> {
                 .possible_values(&["primary", "secondary", "clipboard"])
                 .help("Output to selection (defaults to clipboard)"),
         )
+        .arg(
+            Arg::with_name("multi")
+                .short("m")
+                .long("multi")
+                .help("Pick multiple colors until Enter or Escape is pressed"),
+        )
+        .arg(
+            Arg::with_name("average")
+                .short("a")
+                .long("average")
+                .takes_value(true)
+                .value_name("METHOD")
+                .possible_values(&["mean", "median"])
+                .help("How to combine the colors of a dragged area (defaults to mean)"),
+        )
         .arg(
             Arg::with_name("scale")
                 .short("S")
@@ -52,4 +70,143 @@ pub fn get_cli() -> App<'static, 'static> {
                 .value_name("PREVIEW_SIZE")
                 .help("Size of preview, must be odd (defaults to 255)"),
         )
+        .arg(
+            Arg::with_name("label")
+                .short("l")
+                .long("label")
+                .help("Show the color value inside the preview"),
+        )
+        .arg(
+            Arg::with_name("backend")
+                .short("b")
+                .long("backend")
+                .takes_value(true)
+                .value_name("BACKEND")
+                .possible_values(&["auto", "cursor", "window"])
+                .help("How to display the preview (defaults to auto)"),
+        )
+        .arg(
+            Arg::with_name("shape")
+                .long("shape")
+                .takes_value(true)
+                .value_name("SHAPE")
+                .possible_values(&["circle", "square", "rounded"])
+                .help("Shape of the preview (defaults to circle)"),
+        )
+        .arg(
+            Arg::with_name("no_grid")
+                .long("no-grid")
+                .help("Do not draw lines between the magnified pixels"),
+        )
+        .arg(
+            Arg::with_name("border_width")
+                .long("border-width")
+                .takes_value(true)
+                .value_name("WIDTH")
+                .help("Width of the border around the preview (defaults to 1)"),
+        )
+        .arg(
+            Arg::with_name("marker")
+                .long("marker")
+                .takes_value(true)
+                .value_name("MARKER")
+                .possible_values(&["box", "crosshair", "dot"])
+                .help("How the picked pixels are marked in the preview (defaults to box)"),
+        )
+        .arg(
+            Arg::with_name("border_color")
+                .long("border-color")
+                .takes_value(true)
+                .value_name("COLOR")
+                .help("Color of the border of the preview, such as #ffffff"),
+        )
+        .arg(
+            Arg::with_name("grid_color")
+                .long("grid-color")
+                .takes_value(true)
+                .value_name("COLOR")
+                .help("Color of the grid lines of the preview"),
+        )
+        .arg(
+            Arg::with_name("marker_color")
+                .long("marker-color")
+                .takes_value(true)
+                .value_name("COLOR")
+                .help("Color of the marker of the picked pixels"),
+        )
+        .arg(
+            Arg::with_name("sample_size")
+                .short("n")
+                .long("sample-size")
+                .takes_value(true)
+                .value_name("SIZE")
+                .help("Size of the averaged area around the picked pixel, must be odd (defaults to 1)"),
+        )
+        .arg(
+            Arg::with_name("freeze")
+                .long("freeze")
+                .help("Pick from a snapshot of the screen taken at startup"),
+        )
+        .arg(
+            Arg::with_name("no_shm")
+                .long("no-shm")
+                .help("Read the screen without the MIT-SHM extension"),
+        )
+        .arg(
+            Arg::with_name("grab_timeout")
+                .long("grab-timeout")
+                .takes_value(true)
+                .value_name("DURATION")
+                .help("How long to retry grabbing the pointer, e.g. 500ms (defaults to 0)"),
+        )
+        .arg(
+            Arg::with_name("at")
+                .long("at")
+                .takes_value(true)
+                .value_name("X,Y")
+                .allow_hyphen_values(true)
+                .help("Pick the color at the given coordinates without interaction")
+                .conflicts_with_all(&["at_pointer", "multi"]),
+        )
+        .arg(
+            Arg::with_name("at_pointer")
+                .long("at-pointer")
+                .help("Pick the color under the pointer without interaction")
+                .conflicts_with_all(&["at", "multi"]),
+        )
+        .arg(
+            Arg::with_name("screen")
+                .long("screen")
+                .takes_value(true)
+                .value_name("N")
+                .help("Only pick from the given X screen"),
+        )
+        .arg(
+            Arg::with_name("window")
+                .short("w")
+                .long("window")
+                .takes_value(true)
+                .value_name("ID")
+                .help("Pick from the contents of the given window")
+                .conflicts_with("select_window"),
+        )
+        .arg(
+            Arg::with_name("select_window")
+                .short("W")
+                .long("select-window")
+                .help("Click a window to pick from its contents")
+                .conflicts_with("window"),
+        )
+        .arg(
+            Arg::with_name("watch_pointer")
+                .long("watch-pointer")
+                .help("Print the color under the pointer whenever it changes")
+                .conflicts_with_all(&["multi", "at", "at_pointer", "selection"]),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .help("Print colors as JSON objects in --watch-pointer mode")
+                .requires("watch_pointer"),
+        )
 }
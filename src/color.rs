@@ -1,8 +1,12 @@
-use anyhow::{anyhow, Result};
-use std;
+use anyhow::{anyhow, Error, Result};
+use std::str::FromStr;
 use xcb::xproto;
 use xcb::Connection;
 
+use crate::space;
+use crate::visual::ImageLayout;
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Clone, Copy, PartialEq)]
 pub struct ARGB {
     pub a: u8,
@@ -74,6 +78,34 @@ impl ARGB {
     }
 }
 
+impl FromStr for ARGB {
+    type Err = Error;
+
+    /// Parses an opaque color written in hexadecimal as `#rgb` or `#rrggbb`, where the `#` is
+    /// optional
+    fn from_str(s: &str) -> Result<Self> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        let invalid = || anyhow!("Invalid color");
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+
+        let channel = |i: usize, len: usize| {
+            let value = u8::from_str_radix(&hex[i * len..(i + 1) * len], 16).unwrap();
+            if len == 1 {
+                value * 0x11
+            } else {
+                value
+            }
+        };
+        match hex.len() {
+            3 => Ok(ARGB::new(0xff, channel(0, 1), channel(1, 1), channel(2, 1))),
+            6 => Ok(ARGB::new(0xff, channel(0, 2), channel(1, 2), channel(2, 2))),
+            _ => Err(invalid()),
+        }
+    }
+}
+
 impl From<ARGB> for u32 {
     fn from(color: ARGB) -> u32 {
         u32::from(color.a) << 24
@@ -83,11 +115,189 @@ impl From<ARGB> for u32 {
     }
 }
 
-pub fn window_rect(
+/// Color with 16 bits per channel, which keeps the full precision of deep-color displays. Channels
+/// are scaled to the full 16-bit range regardless of the precision they were read with.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Color {
+    pub a: u16,
+    pub r: u16,
+    pub g: u16,
+    pub b: u16,
+    /// Number of bits per channel the color was read with
+    pub bits: u8,
+}
+
+impl Color {
+    pub const fn new(a: u16, r: u16, g: u16, b: u16, bits: u8) -> Color {
+        Color { a, r, g, b, bits }
+    }
+
+    /// Scales a channel value with `bits` bits to 16 bits
+    pub fn expand(value: u32, bits: u8) -> u16 {
+        let max = (1u64 << bits) - 1;
+        ((value as u64 * 0xffff + max / 2) / max) as u16
+    }
+
+    /// Scales a 16-bit channel value down to `bits` bits
+    pub fn reduce(value: u16, bits: u8) -> u32 {
+        let max = (1u64 << bits) - 1;
+        ((value as u64 * max + 0x7fff) / 0xffff) as u32
+    }
+
+    // The sRGB channels scaled to the range from 0 to 1, along with their maximum and minimum
+    fn unit_channels(self) -> ([f64; 3], f64, f64) {
+        let channels = [self.r, self.g, self.b].map(|value| f64::from(value) / 65535.0);
+        let max = channels.iter().copied().fold(0.0, f64::max);
+        let min = channels.iter().copied().fold(1.0, f64::min);
+        (channels, max, min)
+    }
+
+    /// Hue in degrees from 0 to 360, which is 0 for grays
+    pub fn hue(self) -> f64 {
+        let ([r, g, b], max, min) = self.unit_channels();
+        let chroma = max - min;
+        let hue = if chroma == 0.0 {
+            0.0
+        } else if max == r {
+            (g - b) / chroma
+        } else if max == g {
+            (b - r) / chroma + 2.0
+        } else {
+            (r - g) / chroma + 4.0
+        };
+        (hue * 60.0).rem_euclid(360.0)
+    }
+
+    /// Saturation and lightness of the HSL model, from 0 to 1
+    pub fn hsl(self) -> (f64, f64) {
+        let (_, max, min) = self.unit_channels();
+        let lightness = (max + min) / 2.0;
+        let saturation = if max == min {
+            0.0
+        } else {
+            (max - min) / (1.0 - (2.0 * lightness - 1.0).abs())
+        };
+        (saturation, lightness)
+    }
+
+    /// Saturation and value of the HSV model, from 0 to 1
+    pub fn hsv(self) -> (f64, f64) {
+        let (_, max, min) = self.unit_channels();
+        let saturation = if max == 0.0 { 0.0 } else { (max - min) / max };
+        (saturation, max)
+    }
+}
+
+impl From<ARGB> for Color {
+    fn from(color: ARGB) -> Color {
+        let expand = |value: u8| Color::expand(value.into(), 8);
+        Color::new(
+            expand(color.a),
+            expand(color.r),
+            expand(color.g),
+            expand(color.b),
+            8,
+        )
+    }
+}
+
+impl From<Color> for ARGB {
+    fn from(color: Color) -> ARGB {
+        let reduce = |value| Color::reduce(value, 8) as u8;
+        ARGB::new(
+            reduce(color.a),
+            reduce(color.r),
+            reduce(color.g),
+            reduce(color.b),
+        )
+    }
+}
+
+// Converts an sRGB encoded channel value into linear light
+fn to_linear(value: u16) -> f64 {
+    space::srgb_to_linear(f64::from(value) / 65535.0)
+}
+
+// Converts a linear light channel value back into sRGB encoding with `bits` bits of precision
+fn from_linear(value: f64, bits: u8) -> u16 {
+    let max = ((1u32 << bits) - 1) as f64;
+    let value = space::linear_to_srgb(value);
+    Color::expand((value * max).round().clamp(0.0, max) as u32, bits)
+}
+
+/// Method for combining an area of pixels into a single color
+#[derive(Clone, Copy)]
+pub enum Average {
+    Mean,
+    Median,
+}
+
+impl FromStr for Average {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "mean" => Ok(Average::Mean),
+            "median" => Ok(Average::Median),
+            _ => Err(anyhow!("Invalid average")),
+        }
+    }
+}
+
+impl Average {
+    /// Combines `colors` into a single color. Averaging is done in linear light and the result
+    /// keeps the precision of the combined colors. The mean weights colors by their opacity, so
+    /// transparent pixels add to the alpha channel but not to the color.
+    pub fn apply(self, colors: &[Color]) -> Color {
+        assert!(!colors.is_empty(), "cannot average an empty area");
+        let bits = colors.iter().map(|c| c.bits).max().unwrap();
+
+        match self {
+            Average::Mean => {
+                let alpha: f64 = colors.iter().map(|c| f64::from(c.a)).sum();
+                // when every color is fully transparent, they count the same
+                let weight = |c: &Color| if alpha > 0.0 { f64::from(c.a) } else { 1.0 };
+                let total: f64 = colors.iter().map(weight).sum();
+                let mean = |channel: fn(&Color) -> u16| {
+                    let sum: f64 = colors
+                        .iter()
+                        .map(|c| weight(c) * to_linear(channel(c)))
+                        .sum();
+                    from_linear(sum / total, bits)
+                };
+                Color::new(
+                    (alpha / colors.len() as f64).round() as u16,
+                    mean(|c| c.r),
+                    mean(|c| c.g),
+                    mean(|c| c.b),
+                    bits,
+                )
+            }
+            // the transfer function is monotonic so the median is the same in linear light
+            Average::Median => {
+                let median = |channel: fn(&Color) -> u16| {
+                    let mut values: Vec<u16> = colors.iter().map(channel).collect();
+                    values.sort_unstable();
+                    values[values.len() / 2]
+                };
+                Color::new(
+                    median(|c| c.a),
+                    median(|c| c.r),
+                    median(|c| c.g),
+                    median(|c| c.b),
+                    bits,
+                )
+            }
+        }
+    }
+}
+
+/// Reads the pixel values of a rectangle of `window`, row by row
+pub fn window_pixels(
     conn: &Connection,
     window: xproto::Window,
     (x, y, width, height): (i16, i16, u16, u16),
-) -> Result<Vec<ARGB>> {
+) -> Result<Vec<u32>> {
     let reply = xproto::get_image(
         conn,
         xproto::IMAGE_FORMAT_Z_PIXMAP as u8,
@@ -96,22 +306,12 @@ pub fn window_rect(
         y,
         width,
         height,
-        std::u32::MAX,
+        u32::MAX,
     )
     .get_reply()?;
 
-    if reply.depth() != 24 {
-        // TODO: Figure out what to do with these
-        return Err(anyhow!("Unsupported color depth"));
-    }
-
-    let data = reply.data();
-    let mut pixels = Vec::with_capacity(data.len());
-    for chunk in data.chunks(4) {
-        pixels.push(ARGB::new(0xff, chunk[2], chunk[1], chunk[0]));
-    }
-
-    Ok(pixels)
+    let layout = ImageLayout::new(conn, reply.depth())?;
+    Ok(layout.read(reply.data(), width as usize))
 }
 
 #[test]
@@ -122,3 +322,102 @@ fn test_compaction() {
     assert!(!ARGB::new(0xff, 0xf7, 0xf7, 0xf7).is_compactable());
     assert!(!ARGB::new(0xff, 0xff, 0xf7, 0xff).is_compactable());
 }
+
+#[test]
+fn test_parse_argb() {
+    let parse = |s: &str| s.parse::<ARGB>().ok().map(u32::from);
+
+    assert_eq!(parse("#ff8000"), Some(0xffff_8000));
+    assert_eq!(parse("00Ff00"), Some(0xff00_ff00));
+    assert_eq!(parse("#f80"), Some(0xffff_8800));
+    assert_eq!(parse("#ff800"), None);
+    assert_eq!(parse("#ff80zz"), None);
+    assert_eq!(parse("+f80"), None);
+    assert_eq!(parse(""), None);
+}
+
+#[test]
+fn test_hsl_hsv() {
+    let color = |r, g, b| Color::from(ARGB::new(0xff, r, g, b));
+    let round = |(a, b): (f64, f64)| ((a * 100.0).round(), (b * 100.0).round());
+
+    let steel = color(0x4d, 0x80, 0xb3);
+    assert_eq!(steel.hue().round(), 210.0);
+    assert_eq!(round(steel.hsl()), (40.0, 50.0));
+    assert_eq!(round(steel.hsv()), (57.0, 70.0));
+
+    assert_eq!(color(0xff, 0, 0).hue(), 0.0);
+    assert_eq!(color(0, 0xff, 0).hue(), 120.0);
+    assert_eq!(color(0, 0, 0xff).hue(), 240.0);
+    assert_eq!(color(0xff, 0, 0x80).hue().round(), 330.0);
+    assert_eq!(round(color(0xff, 0, 0).hsl()), (100.0, 50.0));
+
+    // grays have neither hue nor saturation
+    let gray = color(0x80, 0x80, 0x80);
+    assert_eq!((gray.hue(), gray.hsl().0, gray.hsv().0), (0.0, 0.0, 0.0));
+    assert_eq!(round(Color::from(ARGB::WHITE).hsl()), (0.0, 100.0));
+    assert_eq!(Color::from(ARGB::BLACK).hsv(), (0.0, 0.0));
+}
+
+#[test]
+fn test_average() {
+    let average = |average: Average, colors: &[ARGB]| {
+        let colors: Vec<Color> = colors.iter().map(|&c| Color::from(c)).collect();
+        ARGB::from(average.apply(&colors))
+    };
+
+    let mean = average(Average::Mean, &[ARGB::BLACK, ARGB::WHITE]);
+    assert_eq!((mean.r, mean.g, mean.b), (0xbc, 0xbc, 0xbc));
+
+    let colors = [
+        ARGB::new(0xff, 10, 200, 0),
+        ARGB::new(0xff, 20, 100, 0),
+        ARGB::new(0xff, 255, 0, 0),
+    ];
+    let median = average(Average::Median, &colors);
+    assert_eq!((median.r, median.g, median.b), (20, 100, 0));
+
+    let single = average(Average::Mean, &[ARGB::new(0xff, 0x12, 0x34, 0x56)]);
+    assert_eq!((single.r, single.g, single.b), (0x12, 0x34, 0x56));
+
+    // 10-bit colors are averaged without losing precision
+    let deep = Color::new(0xffff, Color::expand(0x201, 10), 0, 0, 10);
+    let mean = Average::Mean.apply(&[deep, deep]);
+    assert_eq!((Color::reduce(mean.r, 10), mean.bits), (0x201, 10));
+
+    // transparent pixels lower the alpha without changing the color
+    let mean = average(Average::Mean, &[ARGB::TRANSPARENT, ARGB::WHITE]);
+    assert!(mean == ARGB::new(0x80, 0xff, 0xff, 0xff));
+    let median = average(
+        Average::Median,
+        &[ARGB::TRANSPARENT, ARGB::WHITE, ARGB::WHITE],
+    );
+    assert!(median == ARGB::WHITE);
+}
+
+#[test]
+fn test_linear_round_trip() {
+    for value in 0..=255 {
+        let value = Color::expand(value, 8);
+        assert_eq!(from_linear(to_linear(value), 8), value);
+    }
+    for value in 0..=1023 {
+        let value = Color::expand(value, 10);
+        assert_eq!(from_linear(to_linear(value), 10), value);
+    }
+}
+
+#[test]
+fn test_precision() {
+    for value in 0..=255 {
+        let color = ARGB::new(0xff, value, value, value);
+        assert!(ARGB::from(Color::from(color)) == color);
+    }
+    for bits in 1..=16 {
+        for value in (0..1 << bits).step_by(7) {
+            assert_eq!(Color::reduce(Color::expand(value, bits), bits), value);
+        }
+    }
+    assert_eq!(Color::expand(0x3ff, 10), 0xffff);
+    assert_eq!(Color::reduce(0x8000, 10), 0x200);
+}
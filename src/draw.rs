@@ -1,81 +1,305 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error, Result};
+
 use crate::color::ARGB;
+use crate::font::{self, GLYPH_HEIGHT, GLYPH_WIDTH};
 use crate::pixel::PixelSquare;
 
-#[inline]
-fn is_inside_circle(x: isize, y: isize, r: isize) -> bool {
-    (x - r).pow(2) + (y - r).pow(2) < r.pow(2)
+/// Outline of the magnifying glass
+#[derive(Clone, Copy, PartialEq)]
+pub enum Shape {
+    Circle,
+    Square,
+    /// Square with rounded corners
+    Rounded,
+}
+
+impl FromStr for Shape {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "circle" => Ok(Shape::Circle),
+            "square" => Ok(Shape::Square),
+            "rounded" => Ok(Shape::Rounded),
+            _ => Err(anyhow!("Invalid shape")),
+        }
+    }
+}
+
+/// How the sampled pixels in the middle of the magnifying glass are marked
+#[derive(Clone, Copy, PartialEq)]
+pub enum Marker {
+    /// Outline around the sampled block
+    Box,
+    /// Lines from the edge of the glass up to the sampled block
+    Crosshair,
+    /// Dot in the middle of the center pixel
+    Dot,
+}
+
+impl FromStr for Marker {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "box" => Ok(Marker::Box),
+            "crosshair" => Ok(Marker::Crosshair),
+            "dot" => Ok(Marker::Dot),
+            _ => Err(anyhow!("Invalid marker")),
+        }
+    }
+}
+
+/// Looks of the magnifying glass. Colors that are not set contrast with the pixels below them.
+#[derive(Clone, Copy)]
+pub struct Appearance {
+    pub shape: Shape,
+    /// Draw lines between the magnified pixels
+    pub grid: bool,
+    pub border_width: usize,
+    pub marker: Marker,
+    pub border_color: Option<ARGB>,
+    pub grid_color: Option<ARGB>,
+    pub marker_color: Option<ARGB>,
+}
+
+// Whether the pixel at `x`, `y` lies inside `shape` when it fills a square that is `width` pixels
+// wide, shrunk by `inset` pixels on every side
+fn is_inside(shape: Shape, x: isize, y: isize, width: isize, inset: isize) -> bool {
+    let (x, y, size) = (x - inset, y - inset, width - 2 * inset);
+    if x < 0 || y < 0 || x >= size || y >= size {
+        return false;
+    }
+
+    let radius = match shape {
+        Shape::Circle => size / 2,
+        Shape::Square => 0,
+        Shape::Rounded => size / 8,
+    };
+    // distance to the center of the nearest corner arc, which is the pixel itself away from the
+    // corners
+    let dx = x - x.clamp(radius, size - 1 - radius);
+    let dy = y - y.clamp(radius, size - 1 - radius);
+    4 * (dx.pow(2) + dy.pow(2)) < (2 * radius + 1).pow(2)
 }
 
 #[inline]
-fn border_color(color: ARGB) -> u32 {
+fn contrast_color(color: ARGB) -> ARGB {
     if color.is_dark() {
-        ARGB::WHITE.into()
+        ARGB::WHITE
     } else {
-        ARGB::BLACK.into()
+        ARGB::BLACK
     }
 }
 
+// Colors of the diagonal stripes drawn where there are no pixels to show, such as outside the
+// screen or between monitors
+const OFFSCREEN_STRIPES: [ARGB; 2] = [
+    ARGB::new(0xff, 0x40, 0x40, 0x40),
+    ARGB::new(0xff, 0x60, 0x60, 0x60),
+];
+const OFFSCREEN_STRIPE_WIDTH: isize = 4;
+
+/// Draws the screenshot magnified into a glass. Transparent pixels of the screenshot mark areas
+/// without contents and are drawn as stripes.
 pub fn draw_magnifying_glass(
     cursor: &mut PixelSquare<&mut [u32]>,
     screenshot: &PixelSquare<&[ARGB]>,
     pixel_size: usize,
+    sample_size: usize,
+    appearance: &Appearance,
 ) {
-    assert!(pixel_size % 2 != 0, "pixel_size must be odd");
-    assert!(cursor.width() % 2 != 0, "cursor.width must be odd");
-    assert!(screenshot.width() % 2 != 0, "screenshot.width must be odd");
+    assert!(!pixel_size.is_multiple_of(2), "pixel_size must be odd");
+    assert!(
+        !cursor.width().is_multiple_of(2),
+        "cursor.width must be odd"
+    );
+    assert!(
+        !screenshot.width().is_multiple_of(2),
+        "screenshot.width must be odd"
+    );
 
-    let transparent: u32 = ARGB::TRANSPARENT.into();
+    assert!(!sample_size.is_multiple_of(2), "sample_size must be odd");
 
     let pixel_size = pixel_size as isize;
     let cursor_width = cursor.width() as isize;
     let screenshot_width = screenshot.width() as isize;
-
-    let border_width = 1;
-    let border_radius = cursor_width / 2;
-    let content_radius = border_radius - border_width;
+    let border_width = appearance.border_width as isize;
 
     let cursor_center = cursor_width / 2;
     let cursor_center_pixel = cursor_center - pixel_size / 2;
     let screenshot_center = screenshot_width / 2;
     let offset = screenshot_center * pixel_size - cursor_center_pixel;
 
+    // the block of pixels that gets sampled when picking
+    let sample_start = cursor_center_pixel - (sample_size as isize / 2) * pixel_size;
+    let sample_end = sample_start + sample_size as isize * pixel_size;
+    let dot_radius = pixel_size / 6;
+
     for cx in 0..cursor_width {
         for cy in 0..cursor_width {
             // screenshot coordinates
             let sx = ((cx + offset) / pixel_size) as usize;
             let sy = ((cy + offset) / pixel_size) as usize;
-            let screenshot_color = screenshot[(sx, sy)];
+            let screenshot_color = match screenshot[(sx, sy)] {
+                ARGB::TRANSPARENT => {
+                    OFFSCREEN_STRIPES[((cx + cy) / OFFSCREEN_STRIPE_WIDTH % 2) as usize]
+                }
+                color => color,
+            };
+
+            let is_sample_x = cx >= sample_start && cx <= sample_end;
+            let is_sample_y = cy >= sample_start && cy <= sample_end;
+            let is_marker = match appearance.marker {
+                Marker::Box => {
+                    let is_sample_edge = cx == sample_start
+                        || cx == sample_end
+                        || cy == sample_start
+                        || cy == sample_end;
+                    is_sample_x && is_sample_y && is_sample_edge
+                }
+                Marker::Crosshair => {
+                    (cy == cursor_center && !is_sample_x) || (cx == cursor_center && !is_sample_y)
+                }
+                Marker::Dot => {
+                    (cx - cursor_center).abs() <= dot_radius
+                        && (cy - cursor_center).abs() <= dot_radius
+                }
+            };
+            let is_grid_line = appearance.grid
+                && ((cx + offset) % pixel_size == 0 || (cy + offset) % pixel_size == 0);
 
             // set cursor pixel
-            cursor[(cx as usize, cy as usize)] = if is_inside_circle(cx, cy, content_radius) {
-                let is_grid_line =
-                    (cx + offset) % pixel_size == 0 || (cy + offset) % pixel_size == 0;
-
-                if is_grid_line {
-                    let is_center_x =
-                        cx >= cursor_center_pixel && cx <= cursor_center_pixel + pixel_size;
-                    let is_center_y =
-                        cy >= cursor_center_pixel && cy <= cursor_center_pixel + pixel_size;
-
-                    // center pixel's border color
-                    if is_center_x && is_center_y {
-                        border_color(screenshot_color)
-                    } else {
-                        // grid color
+            let color = if is_inside(appearance.shape, cx, cy, cursor_width, border_width) {
+                if is_marker {
+                    appearance
+                        .marker_color
+                        .unwrap_or_else(|| contrast_color(screenshot_color))
+                } else if is_grid_line {
+                    appearance.grid_color.unwrap_or_else(|| {
                         if screenshot_color.is_dark() {
-                            screenshot_color.lighten(0.2).into()
+                            screenshot_color.lighten(0.2)
                         } else {
-                            screenshot_color.darken(0.2).into()
+                            screenshot_color.darken(0.2)
                         }
-                    }
+                    })
                 } else {
-                    screenshot_color.into()
+                    screenshot_color
                 }
-            } else if is_inside_circle(cx + border_width, cy + border_width, border_radius) {
-                border_color(screenshot_color)
+            } else if is_inside(appearance.shape, cx, cy, cursor_width, 0) {
+                appearance
+                    .border_color
+                    .unwrap_or_else(|| contrast_color(screenshot_color))
+            } else {
+                ARGB::TRANSPARENT
+            };
+            cursor[(cx as usize, cy as usize)] = color.into();
+        }
+    }
+}
+
+// Draws a strip showing a swatch of `color` and `text` into the lower part of the magnifying glass
+pub fn draw_label(
+    cursor: &mut PixelSquare<&mut [u32]>,
+    appearance: &Appearance,
+    color: ARGB,
+    text: &str,
+) {
+    let width = cursor.width();
+    let chars: Vec<char> = text.chars().collect();
+
+    // scale the font with the cursor, but keep the strip narrower than the glass
+    let mut font_scale = (width / 100).max(1);
+    let strip_width = |scale: usize| {
+        let padding = 2 * scale;
+        let swatch = GLYPH_HEIGHT * scale;
+        let text_width = (chars.len() * (GLYPH_WIDTH + 1)).saturating_sub(1) * scale;
+        padding + swatch + padding + text_width + padding
+    };
+    while font_scale > 1 && strip_width(font_scale) > width * 3 / 4 {
+        font_scale -= 1;
+    }
+
+    let padding = 2 * font_scale;
+    let swatch = GLYPH_HEIGHT * font_scale;
+    let strip_width = strip_width(font_scale);
+    let strip_height = padding + swatch + padding;
+
+    let strip_x = (width as isize - strip_width as isize) / 2;
+    let strip_y = (width * 2 / 3) as isize;
+    let text_x = strip_x + (padding + swatch + padding) as isize;
+    let text_y = strip_y + padding as isize;
+
+    let background: u32 = ARGB::BLACK.into();
+    let foreground: u32 = ARGB::WHITE.into();
+
+    for y in strip_y..strip_y + strip_height as isize {
+        for x in strip_x..strip_x + strip_width as isize {
+            let inset = appearance.border_width as isize;
+            if !is_inside(appearance.shape, x, y, width as isize, inset) {
+                continue;
+            }
+
+            let sx = x - strip_x - padding as isize;
+            let sy = y - text_y;
+            let is_swatch =
+                (0..swatch as isize).contains(&sx) && (0..swatch as isize).contains(&sy);
+
+            // text is drawn in cells of the glyph width plus one column of spacing
+            let tx = (x - text_x) / font_scale as isize;
+            let ty = (y - text_y) / font_scale as isize;
+            let cell = GLYPH_WIDTH as isize + 1;
+            let is_text = x >= text_x
+                && (0..GLYPH_HEIGHT as isize).contains(&ty)
+                && tx % cell < GLYPH_WIDTH as isize
+                && chars
+                    .get((tx / cell) as usize)
+                    .is_some_and(|&c| font::is_set(c, (tx % cell) as usize, ty as usize));
+
+            cursor[y as usize * width + x as usize] = if is_swatch {
+                ARGB { a: 0xff, ..color }.into()
+            } else if is_text {
+                foreground
             } else {
-                transparent
+                background
             };
         }
     }
 }
+
+#[test]
+fn test_is_inside() {
+    let inside = |shape, width, inset| -> Vec<bool> {
+        (0..width * width)
+            .map(|i| is_inside(shape, i % width, i / width, width, inset))
+            .collect()
+    };
+    let (o, x) = (false, true);
+
+    #[rustfmt::skip]
+    assert_eq!(inside(Shape::Circle, 5, 0), [
+        o, x, x, x, o,
+        x, x, x, x, x,
+        x, x, x, x, x,
+        x, x, x, x, x,
+        o, x, x, x, o,
+    ]);
+    #[rustfmt::skip]
+    assert_eq!(inside(Shape::Circle, 5, 1), [
+        o, o, o, o, o,
+        o, x, x, x, o,
+        o, x, x, x, o,
+        o, x, x, x, o,
+        o, o, o, o, o,
+    ]);
+    assert!(inside(Shape::Square, 5, 0).iter().all(|&inside| inside));
+    assert_eq!(inside(Shape::Square, 5, 2), inside(Shape::Circle, 5, 2));
+    assert_eq!(inside(Shape::Square, 5, 3), [false; 25]);
+
+    // only the very corners of a large rounded square are cut off
+    let rounded = inside(Shape::Rounded, 33, 0);
+    assert!(!rounded[0] && rounded[4 * 33 + 4] && rounded[16]);
+    assert!(!rounded[32] && !rounded[32 * 33] && !rounded[33 * 33 - 1]);
+}
@@ -5,7 +5,7 @@ use std::{fmt, iter};
 use nom::branch::alt;
 use nom::bytes::complete::{tag, take_till1};
 use nom::character::complete::{anychar, digit1};
-use nom::combinator::{all_consuming, complete, map, map_res, opt, value};
+use nom::combinator::{all_consuming, complete, map, map_opt, map_res, opt, value};
 use nom::error::{FromExternalError, ParseError};
 use nom::multi::many0;
 use nom::sequence::{preceded, terminated, tuple};
@@ -13,22 +13,65 @@ use nom::IResult;
 
 use anyhow::{anyhow, Error, Result};
 
-use crate::color::ARGB;
+use crate::color::{Color, ARGB};
+use crate::names;
+use crate::space;
 
 pub struct FormatString(Vec<FormatPart>);
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 enum Channel {
+    A,
     R,
     G,
     B,
+    // in degrees
+    Hue,
+    // in percent
+    HslSaturation,
+    Lightness,
+    HsvSaturation,
+    Value,
+    // CIE XYZ relative to D65, where white has a Y of 1
+    X,
+    Y,
+    Z,
+    // CIELAB and its cylindrical form LCh
+    LabL,
+    LabA,
+    LabB,
+    LchC,
+    LchH,
+    // OKLab and its cylindrical form OKLCH
+    OklabL,
+    OklabA,
+    OklabB,
+    OklchC,
+    OklchH,
+    // CIEDE2000 difference to the nearest named color
+    NameDistance,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Align {
+    Left,
+    Right,
 }
 
 struct Pad {
     char: char,
+    align: Align,
     len: u16,
 }
 
+// Number of bits a channel is printed with
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Precision {
+    Bits(u8),
+    // the precision the color was read with
+    Native,
+}
+
 #[derive(Clone, Copy)]
 enum NumberFormat {
     LowercaseHex,
@@ -36,6 +79,9 @@ enum NumberFormat {
     Decimal,
     Octal,
     Binary,
+    // a fraction of the range of the channel
+    Fraction,
+    Percent,
 }
 
 enum FormatPart {
@@ -44,10 +90,17 @@ enum FormatPart {
         channel: Channel,
         format: NumberFormat,
         pad: Option<Pad>,
+        // number of decimal places
+        decimals: Option<usize>,
+        precision: Precision,
+    },
+    // the name of the nearest named color
+    Name {
+        pad: Option<Pad>,
     },
 }
 
-fn literal<'a, E>(input: &'a str) -> IResult<&str, FormatPart, E>
+fn literal<'a, E>(input: &'a str) -> IResult<&'a str, FormatPart, E>
 where
     E: ParseError<&'a str>,
 {
@@ -56,18 +109,45 @@ where
     })(input)
 }
 
-fn channel<'a, E>(input: &'a str) -> IResult<&str, Channel, E>
+fn channel<'a, E>(input: &'a str) -> IResult<&'a str, Channel, E>
 where
     E: ParseError<&'a str>,
 {
-    alt((
+    let argb = alt((
+        value(Channel::A, tag("a")),
         value(Channel::R, tag("r")),
         value(Channel::G, tag("g")),
         value(Channel::B, tag("b")),
-    ))(input)
+    ));
+    let hsl_hsv = alt((
+        value(Channel::Hue, alt((tag("hsl.h"), tag("hsv.h")))),
+        value(Channel::HslSaturation, tag("hsl.s")),
+        value(Channel::Lightness, tag("hsl.l")),
+        value(Channel::HsvSaturation, tag("hsv.s")),
+        value(Channel::Value, tag("hsv.v")),
+    ));
+    let cie = alt((
+        value(Channel::X, tag("xyz.x")),
+        value(Channel::Y, tag("xyz.y")),
+        value(Channel::Z, tag("xyz.z")),
+        value(Channel::LabL, alt((tag("lab.l"), tag("lch.l")))),
+        value(Channel::LabA, tag("lab.a")),
+        value(Channel::LabB, tag("lab.b")),
+        value(Channel::LchC, tag("lch.c")),
+        value(Channel::LchH, tag("lch.h")),
+    ));
+    let ok = alt((
+        value(Channel::OklabL, alt((tag("oklab.l"), tag("oklch.l")))),
+        value(Channel::OklabA, tag("oklab.a")),
+        value(Channel::OklabB, tag("oklab.b")),
+        value(Channel::OklchC, tag("oklch.c")),
+        value(Channel::OklchH, tag("oklch.h")),
+    ));
+    let name = value(Channel::NameDistance, tag("name.de"));
+    alt((argb, hsl_hsv, cie, ok, name))(input)
 }
 
-fn format<'a, E>(input: &'a str) -> IResult<&str, NumberFormat, E>
+fn format<'a, E>(input: &'a str) -> IResult<&'a str, NumberFormat, E>
 where
     E: ParseError<&'a str>,
 {
@@ -77,35 +157,93 @@ where
         value(NumberFormat::Octal, tag("o")),
         value(NumberFormat::Binary, tag("B")),
         value(NumberFormat::Decimal, tag("d")),
+        value(NumberFormat::Fraction, tag("f")),
+        value(NumberFormat::Percent, tag("%")),
     ))(input)
 }
 
-fn pad<'a, E>(input: &'a str) -> IResult<&str, Pad, E>
+fn precision<'a, E>(input: &'a str) -> IResult<&'a str, Precision, E>
+where
+    E: ParseError<&'a str>,
+{
+    let bits = map_opt(digit1, |s: &str| {
+        s.parse::<u8>()
+            .ok()
+            .filter(|bits| (1..=16).contains(bits))
+            .map(Precision::Bits)
+    });
+    preceded(tag(":"), alt((value(Precision::Native, tag("*")), bits)))(input)
+}
+
+fn decimals<'a, E>(input: &'a str) -> IResult<&'a str, usize, E>
 where
     E: ParseError<&'a str> + FromExternalError<&'a str, ParseIntError>,
 {
+    preceded(
+        tag("."),
+        map_res(digit1, |s: &str| s.parse::<u8>().map(usize::from)),
+    )(input)
+}
+
+fn pad<'a, E>(input: &'a str) -> IResult<&'a str, Pad, E>
+where
+    E: ParseError<&'a str> + FromExternalError<&'a str, ParseIntError>,
+{
+    let align = alt((value(Align::Left, tag("<")), value(Align::Right, tag(">"))));
     let digit = map_res(digit1, |s: &str| s.parse::<u16>());
-    map(tuple((anychar, digit)), |(char, len)| Pad { char, len })(input)
+    map_opt(
+        tuple((anychar, opt(align), digit)),
+        |(char, align, len)| match (char, align) {
+            // without an alignment, `.` starts the number of decimal places
+            ('.', None) => None,
+            (char, align) => Some(Pad {
+                char,
+                align: align.unwrap_or(Align::Right),
+                len,
+            }),
+        },
+    )(input)
 }
 
-fn expansion<'a, E>(input: &'a str) -> IResult<&str, FormatPart, E>
+fn expansion<'a, E>(input: &'a str) -> IResult<&'a str, FormatPart, E>
 where
     E: ParseError<&'a str> + FromExternalError<&'a str, ParseIntError>,
 {
     let escape = map(tag("%%"), |_| FormatPart::Literal("%".to_owned()));
-    let inner = complete(map(
-        tuple((opt(pad), opt(format), channel)),
-        |(pad, format, channel)| FormatPart::Expansion {
-            channel,
-            pad,
-            format: format.unwrap_or(NumberFormat::Decimal),
+    // channels are tried first, so `hsl.h` is a channel rather than `sl.h` in lowercase hex
+    let format_and_channel = alt((
+        map(channel, |channel| (None, channel)),
+        tuple((map(format, Some), channel)),
+    ));
+    let inner = complete(map_opt(
+        tuple((opt(pad), opt(decimals), format_and_channel, opt(precision))),
+        |(pad, decimals, (format, channel), precision)| {
+            // the bit precision only applies to the alpha, red, green and blue channels
+            if precision.is_some() && !channel.is_argb() {
+                return None;
+            }
+            let format = format.unwrap_or(NumberFormat::Decimal);
+            // only integers can be printed in hexadecimal, octal or binary
+            if format.is_integer() && (decimals.is_some() || channel.is_fractional()) {
+                return None;
+            }
+            Some(FormatPart::Expansion {
+                channel,
+                pad,
+                format,
+                decimals,
+                precision: precision.unwrap_or(Precision::Bits(8)),
+            })
         },
     ));
-    let expansion = preceded(tag("%{"), terminated(inner, tag("}")));
+    let name = map(terminated(opt(pad), tag("name")), |pad| FormatPart::Name {
+        pad,
+    });
+    let expansion = preceded(tag("%{"), terminated(alt((inner, name)), tag("}")));
     alt((escape, expansion))(input)
 }
 
-fn parse_format_string<'a, E>(input: &'a str) -> IResult<&str, FormatString, E>
+fn parse_format_string<'a, E>(input: &'a str) -> IResult<&'a str, FormatString, E>
 where
     E: ParseError<&'a str> + FromExternalError<&'a str, ParseIntError>,
 {
@@ -126,15 +264,116 @@ impl FromStr for FormatString {
 }
 
 pub trait FormatColor {
-    fn format(&self, color: ARGB) -> String;
+    fn format(&self, color: Color) -> String;
 }
 
 impl Channel {
-    fn extract(&self, color: ARGB) -> u8 {
+    fn is_argb(self) -> bool {
+        matches!(self, Channel::A | Channel::R | Channel::G | Channel::B)
+    }
+
+    fn is_hue(self) -> bool {
+        matches!(self, Channel::Hue | Channel::LchH | Channel::OklchH)
+    }
+
+    fn is_fractional(self) -> bool {
+        self.decimals() > 0
+    }
+
+    // Number of decimal places the channel is printed with by default
+    fn decimals(self) -> usize {
+        match self {
+            Channel::X | Channel::Y | Channel::Z => 4,
+            Channel::LabL | Channel::LabA | Channel::LabB | Channel::LchC | Channel::LchH => 2,
+            Channel::OklabL | Channel::OklabA | Channel::OklabB | Channel::OklchC => 4,
+            Channel::OklchH | Channel::NameDistance => 2,
+            _ => 0,
+        }
+    }
+
+    // The value that makes up 100% of the channel. For the perceptual color spaces these are the
+    // reference ranges of CSS Color Module Level 4.
+    fn range(self, bits: u8) -> f64 {
         match self {
-            Channel::R => color.r,
-            Channel::G => color.g,
-            Channel::B => color.b,
+            Channel::A | Channel::R | Channel::G | Channel::B => {
+                f64::from(Color::reduce(0xffff, bits))
+            }
+            Channel::Hue | Channel::LchH | Channel::OklchH => 360.0,
+            Channel::HslSaturation
+            | Channel::Lightness
+            | Channel::HsvSaturation
+            | Channel::Value
+            | Channel::LabL => 100.0,
+            Channel::X | Channel::Y | Channel::Z | Channel::OklabL => 1.0,
+            Channel::LabA | Channel::LabB => 125.0,
+            Channel::LchC => 150.0,
+            Channel::OklabA | Channel::OklabB | Channel::OklchC => 0.4,
+            // about the difference between black and white
+            Channel::NameDistance => 100.0,
+        }
+    }
+
+    fn extract(self, color: Color, bits: u8) -> f64 {
+        match self {
+            Channel::A => f64::from(Color::reduce(color.a, bits)),
+            Channel::R => f64::from(Color::reduce(color.r, bits)),
+            Channel::G => f64::from(Color::reduce(color.g, bits)),
+            Channel::B => f64::from(Color::reduce(color.b, bits)),
+            Channel::Hue => color.hue(),
+            Channel::HslSaturation => color.hsl().0 * 100.0,
+            Channel::Lightness => color.hsl().1 * 100.0,
+            Channel::HsvSaturation => color.hsv().0 * 100.0,
+            Channel::Value => color.hsv().1 * 100.0,
+            Channel::X => space::xyz(color)[0],
+            Channel::Y => space::xyz(color)[1],
+            Channel::Z => space::xyz(color)[2],
+            Channel::LabL => space::lab(color)[0],
+            Channel::LabA => space::lab(color)[1],
+            Channel::LabB => space::lab(color)[2],
+            Channel::LchC => space::lch(color)[1],
+            Channel::LchH => space::lch(color)[2],
+            Channel::OklabL => space::oklab(color)[0],
+            Channel::OklabA => space::oklab(color)[1],
+            Channel::OklabB => space::oklab(color)[2],
+            Channel::OklchC => space::oklch(color)[1],
+            Channel::OklchH => space::oklch(color)[2],
+            Channel::NameDistance => names::nearest(color).1,
+        }
+    }
+
+    fn format(
+        self,
+        color: Color,
+        format: NumberFormat,
+        decimals: Option<usize>,
+        precision: Precision,
+    ) -> String {
+        let bits = match precision {
+            Precision::Bits(bits) => bits,
+            Precision::Native => color.bits,
+        };
+        let value = self.extract(color, bits);
+        let range = self.range(bits);
+        // the value, the number of decimal places, and the value of a full turn of hue
+        let (value, default_decimals, turn) = match format {
+            NumberFormat::Fraction => (value / range, 3, 1.0),
+            NumberFormat::Percent => (value / range * 100.0, 1, 100.0),
+            _ => (value, self.decimals(), range),
+        };
+        let decimals = decimals.unwrap_or(default_decimals);
+        let scale = 10f64.powi(decimals as i32);
+        let mut value = (value * scale).round() / scale;
+        // a hue that rounds up to a full turn is red again
+        if self.is_hue() && value >= turn {
+            value -= turn;
+        }
+        // adding zero turns a negative zero into a positive one
+        let value = value + 0.0;
+        match format {
+            NumberFormat::Decimal | NumberFormat::Fraction => format!("{:.*}", decimals, value),
+            NumberFormat::Percent => format!("{:.*}%", decimals, value),
+            // the parser only allows these for channels that are never negative
+            format => format.format(value as u32),
         }
     }
 }
@@ -149,38 +388,59 @@ impl NumberFormat {
             NumberFormat::UppercaseHex => format!("{:X}", value),
             NumberFormat::Octal => format!("{:o}", value),
             NumberFormat::Binary => format!("{:b}", value),
-            NumberFormat::Decimal => format!("{}", value),
+            NumberFormat::Decimal | NumberFormat::Fraction | NumberFormat::Percent => {
+                format!("{}", value)
+            }
         }
     }
+
+    fn is_integer(self) -> bool {
+        matches!(
+            self,
+            NumberFormat::LowercaseHex
+                | NumberFormat::UppercaseHex
+                | NumberFormat::Octal
+                | NumberFormat::Binary
+        )
+    }
 }
 
 impl FormatColor for FormatPart {
-    fn format(&self, color: ARGB) -> String {
+    fn format(&self, color: Color) -> String {
         match self {
             FormatPart::Literal(s) => s.clone(),
             FormatPart::Expansion {
                 channel,
                 format,
                 pad,
+                decimals,
+                precision,
             } => {
-                let value = channel.extract(color);
-                let base = format.format(value);
-                if let Some(Pad { char, len }) = *pad {
-                    let base_len = base.chars().count();
-                    if let Some(pad_len) = (len as usize).checked_sub(base_len) {
-                        let mut padded: String = iter::repeat(char).take(pad_len).collect();
-                        padded.push_str(&base);
-                        return padded;
-                    }
-                }
-                base
+                let base = channel.format(color, *format, *decimals, *precision);
+                apply_pad(base, pad)
             }
+            FormatPart::Name { pad } => apply_pad(names::nearest(color).0.to_owned(), pad),
         }
     }
 }
 
+// Pads `base` to the length given by `pad`
+fn apply_pad(base: String, pad: &Option<Pad>) -> String {
+    if let Some(Pad { char, align, len }) = *pad {
+        let base_len = base.chars().count();
+        if let Some(pad_len) = (len as usize).checked_sub(base_len) {
+            let padding = iter::repeat_n(char, pad_len);
+            return match align {
+                Align::Left => base.chars().chain(padding).collect(),
+                Align::Right => padding.chain(base.chars()).collect(),
+            };
+        }
+    }
+    base
+}
+
 impl FormatColor for FormatString {
-    fn format(&self, color: ARGB) -> String {
+    fn format(&self, color: Color) -> String {
         self.0.iter().map(|part| part.format(color)).collect()
     }
 }
@@ -193,11 +453,23 @@ pub enum HexCompaction {
     Full,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 pub enum Format {
     LowercaseHex(HexCompaction),
     UppercaseHex(HexCompaction),
     Plain,
     RGB,
+    HSL,
+    HSV,
+    Lab,
+    LCh,
+    OKLab,
+    OKLCH,
+    // with alpha
+    HexAlpha,
+    RGBA,
+    HexARGB,
+    Name,
 }
 
 impl FromStr for Format {
@@ -210,30 +482,95 @@ impl FromStr for Format {
             "HEX!" => Ok(Format::UppercaseHex(HexCompaction::Compact)),
             "plain" => Ok(Format::Plain),
             "rgb" => Ok(Format::RGB),
+            "hsl" => Ok(Format::HSL),
+            "hsv" => Ok(Format::HSV),
+            "lab" => Ok(Format::Lab),
+            "lch" => Ok(Format::LCh),
+            "oklab" => Ok(Format::OKLab),
+            "oklch" => Ok(Format::OKLCH),
+            "hexa" => Ok(Format::HexAlpha),
+            "rgba" => Ok(Format::RGBA),
+            "argb" => Ok(Format::HexARGB),
+            "name" => Ok(Format::Name),
             _ => Err(anyhow!("Invalid format")),
         }
     }
 }
 
 impl FormatColor for Format {
-    fn format(&self, color: ARGB) -> String {
+    fn format(&self, color: Color) -> String {
+        let channel = |channel: Channel| {
+            channel.format(color, NumberFormat::Decimal, None, Precision::Bits(8))
+        };
+        // the other presets print 8 bits per channel
+        let argb = ARGB::from(color);
+        let (a, r, g, b) = (argb.a, argb.r, argb.g, argb.b);
         match self {
             Format::LowercaseHex(comp) => {
-                if *comp == HexCompaction::Compact && color.is_compactable() {
-                    format!("#{:x}{:x}{:x}", color.r & 0xf, color.g & 0xf, color.b & 0xf)
+                if *comp == HexCompaction::Compact && argb.is_compactable() {
+                    format!("#{:x}{:x}{:x}", r & 0xf, g & 0xf, b & 0xf)
                 } else {
-                    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+                    format!("#{:02x}{:02x}{:02x}", r, g, b)
                 }
             }
             Format::UppercaseHex(comp) => {
-                if *comp == HexCompaction::Compact && color.is_compactable() {
-                    format!("#{:X}{:X}{:X}", color.r & 0xf, color.g & 0xf, color.b & 0xf)
+                if *comp == HexCompaction::Compact && argb.is_compactable() {
+                    format!("#{:X}{:X}{:X}", r & 0xf, g & 0xf, b & 0xf)
                 } else {
-                    format!("#{:02X}{:02X}{:02X}", color.r, color.g, color.b)
+                    format!("#{:02X}{:02X}{:02X}", r, g, b)
                 }
             }
-            Format::Plain => format!("{};{};{}", color.r, color.g, color.b),
-            Format::RGB => format!("rgb({}, {}, {})", color.r, color.g, color.b),
+            Format::Plain => format!("{};{};{}", r, g, b),
+            Format::RGB => format!("rgb({}, {}, {})", r, g, b),
+            Format::HSL => format!(
+                "hsl({}, {}%, {}%)",
+                channel(Channel::Hue),
+                channel(Channel::HslSaturation),
+                channel(Channel::Lightness)
+            ),
+            Format::HSV => format!(
+                "hsv({}, {}%, {}%)",
+                channel(Channel::Hue),
+                channel(Channel::HsvSaturation),
+                channel(Channel::Value)
+            ),
+            Format::Lab => format!(
+                "lab({} {} {})",
+                channel(Channel::LabL),
+                channel(Channel::LabA),
+                channel(Channel::LabB)
+            ),
+            Format::LCh => format!(
+                "lch({} {} {})",
+                channel(Channel::LabL),
+                channel(Channel::LchC),
+                channel(Channel::LchH)
+            ),
+            Format::OKLab => format!(
+                "oklab({} {} {})",
+                channel(Channel::OklabL),
+                channel(Channel::OklabA),
+                channel(Channel::OklabB)
+            ),
+            Format::OKLCH => format!(
+                "oklch({} {} {})",
+                channel(Channel::OklabL),
+                channel(Channel::OklchC),
+                channel(Channel::OklchH)
+            ),
+            Format::HexAlpha => format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a),
+            Format::RGBA => format!(
+                "rgba({}, {}, {}, {})",
+                r,
+                g,
+                b,
+                Channel::A.format(color, NumberFormat::Fraction, Some(3), Precision::Bits(8))
+            ),
+            Format::HexARGB => format!("{:#010X}", u32::from(argb)),
+            Format::Name => {
+                let (name, _) = names::nearest(color);
+                format!("{} (ΔE {})", name, channel(Channel::NameDistance))
+            }
         }
     }
 }
@@ -273,7 +610,12 @@ fn test_expansion() {
     match expansion::<()>("%{04b}").unwrap().1 {
         FormatPart::Expansion {
             channel: Channel::B,
-            pad: Some(Pad { char: '0', len: 4 }),
+            pad:
+                Some(Pad {
+                    char: '0',
+                    align: Align::Right,
+                    len: 4,
+                }),
             ..
         } => (),
         _ => panic!(),
@@ -290,35 +632,203 @@ fn test_format_color() {
     let string: Result<FormatString, _> = "".parse();
     assert!(string.is_ok());
 
-    let should_err = vec!["%{}", "%}", "%{gg}", "%%%{-a}", "%a{}", "%foo"];
+    let should_err = vec![
+        "%{}", "%}", "%{gg}", "%%%{-a}", "%a{}", "%foo", "%{r:}", "%{r:0}", "%{r:17}", "%{r:x}",
+    ];
     for case in should_err {
         assert!(case.parse::<FormatString>().is_err());
     }
 }
 
+#[cfg(test)]
+fn rgb(a: u8, r: u8, g: u8, b: u8) -> Color {
+    ARGB::new(a, r, g, b).into()
+}
+
 #[test]
 fn test_examples_from_readme() {
     let fmt: FormatString = "#%{02hr}%{02hg}%{02hb}".parse().unwrap();
-    assert_eq!(fmt.format(ARGB::new(0xff, 255, 0, 255)), "#ff00ff");
+    assert_eq!(fmt.format(rgb(0xff, 255, 0, 255)), "#ff00ff");
 
     let fmt: FormatString = "#%{02Hr}%{02Hg}%{02Hb}".parse().unwrap();
-    assert_eq!(fmt.format(ARGB::new(0xff, 0, 255, 0)), "#00FF00");
+    assert_eq!(fmt.format(rgb(0xff, 0, 255, 0)), "#00FF00");
 
     let fmt: FormatString = "rgb(%{r}, %{g}, %{b})".parse().unwrap();
-    assert_eq!(
-        fmt.format(ARGB::new(0xff, 255, 255, 255)),
-        "rgb(255, 255, 255)"
-    );
+    assert_eq!(fmt.format(rgb(0xff, 255, 255, 255)), "rgb(255, 255, 255)");
 
     let fmt: FormatString = "%{r};%{g};%{b}".parse().unwrap();
-    assert_eq!(fmt.format(ARGB::new(0xff, 0, 0, 0)), "0;0;0");
+    assert_eq!(fmt.format(rgb(0xff, 0, 0, 0)), "0;0;0");
 
     let fmt: FormatString = "%{r}, %{g}, %{b}".parse().unwrap();
-    assert_eq!(fmt.format(ARGB::new(0xff, 0, 0, 0)), "0, 0, 0");
+    assert_eq!(fmt.format(rgb(0xff, 0, 0, 0)), "0, 0, 0");
 
     let fmt: FormatString = "Green: %{-4g}".parse().unwrap();
-    assert_eq!(fmt.format(ARGB::new(0xff, 0, 7, 0)), "Green: ---7");
+    assert_eq!(fmt.format(rgb(0xff, 0, 7, 0)), "Green: ---7");
 
     let fmt: FormatString = "%{016Br}".parse().unwrap();
-    assert_eq!(fmt.format(ARGB::new(0xff, 3, 0, 0)), "0000000000000011");
+    assert_eq!(fmt.format(rgb(0xff, 3, 0, 0)), "0000000000000011");
+}
+
+#[test]
+fn test_precision() {
+    assert_eq!(precision::<()>(":10").unwrap().1, Precision::Bits(10));
+    assert_eq!(precision::<()>(":*").unwrap().1, Precision::Native);
+
+    let deep = Color::new(
+        0xffff,
+        Color::expand(0x3ff, 10),
+        Color::expand(0x200, 10),
+        0,
+        10,
+    );
+    let fmt: FormatString = "rgb:%{03hr:*}/%{03hg:*}/%{03hb:*}".parse().unwrap();
+    assert_eq!(fmt.format(deep), "rgb:3ff/200/000");
+
+    let fmt: FormatString = "%{r:10} %{g:10} %{g:16} %{g}".parse().unwrap();
+    assert_eq!(fmt.format(deep), "1023 512 32800 128");
+
+    // 8-bit colors print the same as before
+    let fmt: FormatString = "%{r:*} %{r:8} %{r}".parse().unwrap();
+    assert_eq!(fmt.format(rgb(0xff, 0xab, 0, 0)), "171 171 171");
+    assert_eq!(Format::RGB.format(deep), "rgb(255, 128, 0)");
+}
+
+#[test]
+fn test_hsl_hsv() {
+    assert_eq!(channel::<()>("hsl.h").unwrap().1, Channel::Hue);
+    assert_eq!(channel::<()>("hsv.v").unwrap().1, Channel::Value);
+
+    let steel = rgb(0xff, 0x4d, 0x80, 0xb3);
+    assert_eq!(Format::HSL.format(steel), "hsl(210, 40%, 50%)");
+    assert_eq!(Format::HSV.format(steel), "hsv(210, 57%, 70%)");
+    assert_eq!(Format::HSL.format(rgb(0xff, 0, 0, 0)), "hsl(0, 0%, 0%)");
+
+    let fmt: FormatString = "hsl(%{hsl.h}, %{hsl.s}%%, %{hsl.l}%%)".parse().unwrap();
+    assert_eq!(fmt.format(steel), "hsl(210, 40%, 50%)");
+
+    // number formats and padding work as for the other channels, and `h` before a channel is
+    // still the hex format
+    let fmt: FormatString = "%{03hsv.s} %{02hhsl.h} %{hhsv.v} %{hb}".parse().unwrap();
+    assert_eq!(fmt.format(steel), "057 d2 46 b3");
+
+    // hues close to a full turn wrap around to 0
+    assert_eq!(
+        Format::HSL.format(rgb(0xff, 0xff, 0, 1)),
+        "hsl(0, 100%, 50%)"
+    );
+
+    for case in ["%{hsl.h:8}", "%{hsl.x}", "%{hsl}", "%{hsv.l}", "%{Hhsl}"] {
+        assert!(case.parse::<FormatString>().is_err(), "{}", case);
+    }
+}
+
+#[test]
+fn test_perceptual() {
+    assert_eq!(channel::<()>("lch.l").unwrap().1, Channel::LabL);
+    assert_eq!(channel::<()>("oklch.h").unwrap().1, Channel::OklchH);
+
+    let red = rgb(0xff, 0xff, 0, 0);
+    assert_eq!(Format::Lab.format(red), "lab(54.29 80.80 69.89)");
+    assert_eq!(Format::LCh.format(red), "lch(54.29 106.84 40.86)");
+    assert_eq!(Format::OKLab.format(red), "oklab(0.6280 0.2249 0.1258)");
+    assert_eq!(Format::OKLCH.format(red), "oklch(0.6280 0.2577 29.23)");
+
+    // rounding errors in white and grays are not printed as negative zeros
+    let white = rgb(0xff, 0xff, 0xff, 0xff);
+    assert_eq!(Format::Lab.format(white), "lab(100.00 0.00 0.00)");
+    assert_eq!(Format::OKLCH.format(white), "oklch(1.0000 0.0000 0.00)");
+
+    let fmt: FormatString = "%{xyz.x} %{xyz.y} %{xyz.z}".parse().unwrap();
+    assert_eq!(fmt.format(red), "0.4124 0.2126 0.0193");
+
+    let fmt: FormatString = "%{ 8lab.a}|%{ 8lab.b}".parse().unwrap();
+    assert_eq!(fmt.format(rgb(0xff, 0, 0, 0xff)), "   68.29| -112.03");
+
+    // fractional channels are only printed in decimal
+    let fmt: FormatString = "%{dlab.l}".parse().unwrap();
+    assert_eq!(fmt.format(red), "54.29");
+    for case in [
+        "%{hlab.l}",
+        "%{Boklab.a}",
+        "%{lab.l:8}",
+        "%{lab}",
+        "%{xyz.w}",
+    ] {
+        assert!(case.parse::<FormatString>().is_err(), "{}", case);
+    }
+}
+
+#[test]
+fn test_fractions() {
+    assert_eq!(decimals::<()>(".3").unwrap().1, 3);
+    assert_eq!(pad::<()>("*<8").unwrap().1.align, Align::Left);
+    assert_eq!(pad::<()>("<8").unwrap().1.char, '<');
+    assert_eq!(pad::<()>(".>8").unwrap().1.char, '.');
+    assert!(pad::<()>(".3").is_err());
+
+    let color = rgb(0xff, 0x80, 0x40, 0xff);
+    let fmt: FormatString = "%{.3fr} %{fg} %{.0fb}".parse().unwrap();
+    assert_eq!(fmt.format(color), "0.502 0.251 1");
+
+    let fmt: FormatString = "%{.1%r} %{%g} %{.0%hsl.l}".parse().unwrap();
+    assert_eq!(fmt.format(color), "50.2% 25.1% 63%");
+
+    // the number of decimal places works with every channel and number format
+    let fmt: FormatString = "%{.2r} %{.2hsl.s} %{.1lab.l} %{.2oklch.c}".parse().unwrap();
+    assert_eq!(fmt.format(color), "128.00 100.00 45.0 0.26");
+
+    // fractions of the perceptual channels use the reference ranges of CSS Color 4
+    let fmt: FormatString = "%{%lab.b} %{.2foklab.a}".parse().unwrap();
+    assert_eq!(fmt.format(color), "-68.9% 0.23");
+
+    // the fraction of a native precision channel is relative to its maximum
+    let fmt: FormatString = "%{.4fr:*} %{.4fr:16}".parse().unwrap();
+    assert_eq!(fmt.format(color), "0.5020 0.5020");
+
+    let fmt: FormatString = "[%{ <6.1fr}|%{ >6.1fr}|%{06.1fr}]".parse().unwrap();
+    assert_eq!(fmt.format(color), "[0.5   |   0.5|0000.5]");
+
+    for case in [
+        "%{.3hr}",
+        "%{.r}",
+        "%{.fr}",
+        "%{.3}",
+        "%{3.fr}",
+        "%{.256fr}",
+    ] {
+        assert!(case.parse::<FormatString>().is_err(), "{}", case);
+    }
+}
+
+#[test]
+fn test_alpha() {
+    let color = rgb(0x80, 0x46, 0x82, 0xb4);
+    assert_eq!(Format::HexAlpha.format(color), "#4682b480");
+    assert_eq!(Format::RGBA.format(color), "rgba(70, 130, 180, 0.502)");
+    assert_eq!(Format::HexARGB.format(color), "0x804682B4");
+    assert_eq!(
+        Format::RGBA.format(rgb(0xff, 0, 0, 0)),
+        "rgba(0, 0, 0, 1.000)"
+    );
+    assert_eq!(Format::HexARGB.format(rgb(0, 0, 0, 0)), "0x00000000");
+
+    let fmt: FormatString = "%{a} %{02ha} %{a:4} %{.1%a}".parse().unwrap();
+    assert_eq!(fmt.format(color), "128 80 8 50.2%");
+}
+
+#[test]
+fn test_name() {
+    let steel = rgb(0xff, 0x46, 0x82, 0xb4);
+    assert_eq!(Format::Name.format(steel), "steelblue (ΔE 0.00)");
+
+    let fmt: FormatString = "[%{name}|%{ <12name}|%{.1name.de}]".parse().unwrap();
+    assert_eq!(fmt.format(steel), "[steelblue|steelblue   |0.0]");
+
+    let fmt: FormatString = "%{name} (ΔE %{name.de})".parse().unwrap();
+    let color = rgb(0xff, 0x48, 0x80, 0xb0);
+    assert_eq!(fmt.format(color), Format::Name.format(color));
+
+    for case in ["%{hname}", "%{name:8}", "%{.2name}", "%{names}"] {
+        assert!(case.parse::<FormatString>().is_err(), "{}", case);
+    }
 }
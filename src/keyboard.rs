@@ -0,0 +1,34 @@
+use anyhow::Result;
+use xcb::base::Connection;
+use xcb::xproto;
+
+/// Keycode to keysym translation table fetched from the X server.
+pub struct Keymap {
+    min_keycode: xproto::Keycode,
+    keysyms_per_keycode: usize,
+    keysyms: Vec<xproto::Keysym>,
+}
+
+impl Keymap {
+    pub fn new(conn: &Connection) -> Result<Keymap> {
+        let setup = conn.get_setup();
+        let min_keycode = setup.min_keycode();
+        let count = setup.max_keycode() - min_keycode + 1;
+        let reply = xproto::get_keyboard_mapping(conn, min_keycode, count).get_reply()?;
+
+        Ok(Keymap {
+            min_keycode,
+            keysyms_per_keycode: reply.keysyms_per_keycode() as usize,
+            keysyms: reply.keysyms().to_vec(),
+        })
+    }
+
+    /// Returns the unshifted keysym for `keycode`, or `0` (`NoSymbol`) if there is none
+    pub fn keysym(&self, keycode: xproto::Keycode) -> xproto::Keysym {
+        keycode
+            .checked_sub(self.min_keycode)
+            .and_then(|idx| self.keysyms.get(idx as usize * self.keysyms_per_keycode))
+            .copied()
+            .unwrap_or(0)
+    }
+}
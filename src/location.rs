@@ -1,35 +1,400 @@
-use anyhow::{anyhow, Result};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Error, Result};
+use x11::keysym;
 use x11::xcursor::{XcursorImageCreate, XcursorImageDestroy, XcursorImageLoadCursor};
 use xcb::base as xbase;
 use xcb::base::Connection;
 use xcb::xproto;
 
-use crate::color::{self, ARGB};
-use crate::draw::draw_magnifying_glass;
+use crate::color::{Average, Color, ARGB};
+use crate::draw::{draw_label, draw_magnifying_glass, Appearance};
+use crate::format::FormatColor;
+use crate::keyboard::Keymap;
+use crate::monitor;
+use crate::overlay::{self, Overlay};
 use crate::pixel::PixelSquare;
+use crate::source::Source;
 use crate::util::EnsureOdd;
 
 // Left mouse button
 const SELECTION_BUTTON: xproto::Button = 1;
-const GRAB_MASK: u16 = (xproto::EVENT_MASK_BUTTON_PRESS | xproto::EVENT_MASK_POINTER_MOTION) as u16;
+// Right mouse button
+const CANCEL_BUTTON: xproto::Button = 3;
+// Scroll wheel
+const ZOOM_IN_BUTTON: xproto::Button = 4;
+const ZOOM_OUT_BUTTON: xproto::Button = 5;
+
+// Glyph of the crosshair cursor in the standard cursor font
+const CROSSHAIR_GLYPH: u16 = 34;
+
+// Distance between the overlay and the area captured around the pointer
+const OVERLAY_MARGIN: u32 = 16;
+// Time given to the compositing manager or the windows below to repaint the screen after the
+// overlay is hidden, which is a few frames at common refresh rates
+const REPAINT_DELAY: Duration = Duration::from_millis(50);
+
+// Magnification levels the scroll wheel steps through
+const ZOOM_LEVELS: &[u32] = &[1, 2, 3, 4, 6, 8, 12, 16, 24, 32, 48, 64];
+// Time to wait before trying again to grab a device that another client holds
+const GRAB_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+// Time between reads of the pointer position while watching it
+const WATCH_INTERVAL: Duration = Duration::from_millis(16);
+const GRAB_MASK: u16 = (xproto::EVENT_MASK_BUTTON_PRESS
+    | xproto::EVENT_MASK_BUTTON_RELEASE
+    | xproto::EVENT_MASK_POINTER_MOTION) as u16;
+
+/// Settings controlling the behavior of the picker
+pub struct PickerOptions<'a> {
+    pub preview_width: u32,
+    pub scale: u32,
+    /// Keep picking until the user ends the session
+    pub multi: bool,
+    /// How the pixels of a dragged area are combined
+    pub average: Average,
+    /// Width and height of the block of pixels averaged on each pick, which must be odd
+    pub sample_size: u32,
+    /// Formatter for the color value shown inside the magnifier
+    pub label: Option<&'a dyn FormatColor>,
+    /// Where the magnifier is displayed
+    pub backend: Backend,
+    /// Print how long it takes to show each frame of the magnifier
+    pub measure_latency: bool,
+    /// How long to keep trying to grab the pointer and keyboard while another client holds them
+    pub grab_timeout: Duration,
+    /// Looks of the magnifier
+    pub appearance: Appearance,
+}
+
+/// Way of displaying the magnifier
+#[derive(Clone, Copy, PartialEq)]
+pub enum Backend {
+    /// Use an overlay window if a compositing manager is running, and the cursor otherwise
+    Auto,
+    /// Draw the magnifier into the cursor
+    Cursor,
+    /// Draw the magnifier into an overlay window next to the pointer
+    Window,
+}
+
+impl FromStr for Backend {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "auto" => Ok(Backend::Auto),
+            "cursor" => Ok(Backend::Cursor),
+            "window" => Ok(Backend::Window),
+            _ => Err(anyhow!("Invalid backend")),
+        }
+    }
+}
+
+/// Called with the position, in source coordinates, and the color under the pointer every time
+/// it is read
+pub type Watcher<'a> = &'a mut dyn FnMut((i16, i16), Color) -> Result<()>;
+
+// Parameters of the magnifier
+#[derive(Clone, Copy)]
+struct Preview<'a> {
+    width: u32,
+    scale: u32,
+    sample_size: u32,
+    average: Average,
+    label: Option<&'a dyn FormatColor>,
+    appearance: Appearance,
+}
+
+// Rectangle outline drawn straight onto the root window while dragging. Drawing is done with
+// XOR so drawing the same rectangle again erases it.
+struct Outline {
+    root: u32,
+    gc: u32,
+    rect: Option<xproto::Rectangle>,
+}
+
+impl Outline {
+    fn new(conn: &Connection, screen: &xproto::Screen) -> Result<Outline> {
+        let root = screen.root();
+        let gc = conn.generate_id();
+        xproto::create_gc_checked(
+            conn,
+            gc,
+            root,
+            &[
+                (xproto::GC_FUNCTION, xproto::GX_XOR),
+                (
+                    xproto::GC_FOREGROUND,
+                    screen.white_pixel() ^ screen.black_pixel(),
+                ),
+                (
+                    xproto::GC_SUBWINDOW_MODE,
+                    xproto::SUBWINDOW_MODE_INCLUDE_INFERIORS,
+                ),
+            ],
+        )
+        .request_check()?;
+
+        Ok(Outline {
+            root,
+            gc,
+            rect: None,
+        })
+    }
+
+    // Outlines the area between two corners, replacing the previous outline
+    fn draw(&mut self, conn: &Connection, from: (i16, i16), to: (i16, i16)) {
+        self.clear(conn);
+        let (x, y, width, height) = rect_between(from, to);
+        let rect = xproto::Rectangle::new(x, y, width - 1, height - 1);
+        xproto::poly_rectangle(conn, self.root, self.gc, &[rect]);
+        self.rect = Some(rect);
+    }
+
+    fn clear(&mut self, conn: &Connection) {
+        if let Some(rect) = self.rect.take() {
+            xproto::poly_rectangle(conn, self.root, self.gc, &[rect]);
+        }
+    }
+
+    fn free(mut self, conn: &Connection) {
+        self.clear(conn);
+        xproto::free_gc(conn, self.gc);
+    }
+}
+
+// Measures how long it takes to show the magnifier for a new pointer position, from the moment
+// the server generated the motion event until it has processed the new image. Times are taken
+// on the server's clock, so the time the event spent waiting to be read is included.
+struct FrameTimer {
+    // a server timestamp and the moment it was received, to convert between the two clocks
+    epoch: (xproto::Timestamp, Instant),
+    frames: u32,
+    total: Duration,
+    queued: Duration,
+    max: Duration,
+}
+
+impl FrameTimer {
+    fn new(conn: &Connection, screen: &xproto::Screen) -> Result<FrameTimer> {
+        Ok(FrameTimer {
+            epoch: server_time(conn, screen)?,
+            frames: 0,
+            total: Duration::ZERO,
+            queued: Duration::ZERO,
+            max: Duration::ZERO,
+        })
+    }
+
+    // Time between `time`, on the server's clock, and the moment `at`
+    fn since(&self, time: xproto::Timestamp, at: Instant) -> Duration {
+        let (epoch, received) = self.epoch;
+        let elapsed = at.saturating_duration_since(received).as_millis() as u32;
+        // timestamps wrap around, and the estimate can be slightly behind the server's clock
+        let millis = (epoch.wrapping_add(elapsed).wrapping_sub(time) as i32).max(0);
+        Duration::from_millis(millis as u64)
+    }
+
+    fn record(
+        &mut self,
+        conn: &Connection,
+        time: xproto::Timestamp,
+        received: Instant,
+        skipped: usize,
+    ) -> Result<()> {
+        // a round trip makes sure the server has handled all of the requests before it
+        xproto::get_input_focus(conn).get_reply()?;
+        let done = Instant::now();
+        let total = self.since(time, done);
+        let queued = self.since(time, received);
+
+        self.frames += 1;
+        self.total += total;
+        self.queued += queued;
+        self.max = self.max.max(total);
+        eprintln!(
+            "frame {}: {} ms, of which {} ms waiting to be read and {:.1} ms drawing, {} motion \
+             events skipped",
+            self.frames,
+            total.as_millis(),
+            queued.as_millis(),
+            (done - received).as_secs_f64() * 1000.0,
+            skipped
+        );
+        Ok(())
+    }
 
-// Exclusively grabs the pointer so we get all its events
-fn grab_pointer(conn: &Connection, root: u32, cursor: u32) -> Result<()> {
-    let reply = xproto::grab_pointer(
+    fn summary(&self) {
+        if self.frames > 0 {
+            eprintln!(
+                "{} frames, {:.1} ms on average, of which {:.1} ms waiting to be read, {} ms at \
+                 most",
+                self.frames,
+                self.total.as_secs_f64() * 1000.0 / self.frames as f64,
+                self.queued.as_secs_f64() * 1000.0 / self.frames as f64,
+                self.max.as_millis()
+            );
+        }
+    }
+}
+
+// Returns the current time of the server along with the moment it was received. The server only
+// tells its time in events, so a property of a temporary window is changed to get one.
+fn server_time(conn: &Connection, screen: &xproto::Screen) -> Result<(xproto::Timestamp, Instant)> {
+    let window = conn.generate_id();
+    xproto::create_window(
         conn,
-        false,
-        root,
-        GRAB_MASK,
-        xproto::GRAB_MODE_ASYNC as u8,
-        xproto::GRAB_MODE_ASYNC as u8,
-        xbase::NONE,
-        cursor,
-        xbase::CURRENT_TIME,
-    )
-    .get_reply()?;
+        0,
+        window,
+        screen.root(),
+        0,
+        0,
+        1,
+        1,
+        0,
+        xproto::WINDOW_CLASS_INPUT_ONLY as u16,
+        0,
+        &[(xproto::CW_EVENT_MASK, xproto::EVENT_MASK_PROPERTY_CHANGE)],
+    );
+    // appending nothing leaves the property as it is, but still generates the event
+    xproto::change_property(
+        conn,
+        xproto::PROP_MODE_APPEND as u8,
+        window,
+        xproto::ATOM_WM_NAME,
+        xproto::ATOM_STRING,
+        8,
+        &[] as &[u8],
+    );
+    conn.flush();
+
+    let result = loop {
+        match conn.wait_for_event() {
+            Some(event) if event.response_type() == xproto::PROPERTY_NOTIFY => {
+                let event: &xproto::PropertyNotifyEvent = unsafe { xbase::cast_event(&event) };
+                if event.window() == window {
+                    break Ok((event.time(), Instant::now()));
+                }
+            }
+            Some(_) => {}
+            None => break Err(anyhow!("Lost connection to the X server")),
+        }
+    };
+    xproto::destroy_window(conn, window);
+    result
+}
+
+// Skips the motion events that have already arrived after `event`, since only the most recent
+// pointer position needs to be drawn. Returns the latest motion event, the number of events
+// skipped and the first event of another kind, which must be handled next.
+fn coalesce_motion(
+    conn: &Connection,
+    mut event: xbase::GenericEvent,
+) -> (xbase::GenericEvent, usize, Option<xbase::GenericEvent>) {
+    let mut skipped = 0;
+    while let Some(next) = conn.poll_for_event() {
+        if next.response_type() != xproto::MOTION_NOTIFY {
+            return (event, skipped, Some(next));
+        }
+        event = next;
+        skipped += 1;
+    }
+    (event, skipped, None)
+}
+
+// Returns the area spanned by two corners, both of which are included in the area
+fn rect_between((x0, y0): (i16, i16), (x1, y1): (i16, i16)) -> (i16, i16, u16, u16) {
+    let width = (x0 as i32 - x1 as i32).unsigned_abs() + 1;
+    let height = (y0 as i32 - y1 as i32).unsigned_abs() + 1;
+    (x0.min(x1), y0.min(y1), width as u16, height as u16)
+}
+
+// Returns the name a grab status has in the protocol
+fn grab_status_name(status: u8) -> &'static str {
+    match u32::from(status) {
+        xproto::GRAB_STATUS_SUCCESS => "Success",
+        xproto::GRAB_STATUS_ALREADY_GRABBED => "AlreadyGrabbed",
+        xproto::GRAB_STATUS_INVALID_TIME => "InvalidTime",
+        xproto::GRAB_STATUS_NOT_VIEWABLE => "NotViewable",
+        xproto::GRAB_STATUS_FROZEN => "Frozen",
+        _ => "Unknown",
+    }
+}
+
+// Repeats a grab until it succeeds or `timeout` has passed, which gives other clients, such as a
+// hotkey daemon that started us, time to release their grab. Returns the last status.
+fn retry_grab(timeout: Duration, mut grab: impl FnMut() -> Result<u8>) -> Result<u8> {
+    // a timeout that reaches beyond what `Instant` can represent never runs out
+    let deadline = Instant::now().checked_add(timeout);
+    loop {
+        let status = grab()?;
+        let expired = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+        if status == xproto::GRAB_STATUS_SUCCESS as u8 || expired {
+            return Ok(status);
+        }
+        std::thread::sleep(GRAB_RETRY_INTERVAL);
+    }
+}
 
-    if reply.status() != xproto::GRAB_STATUS_SUCCESS as u8 {
-        return Err(anyhow!("Could not grab pointer"));
+// Exclusively grabs the pointer so we get all its events. Grabbing again while the grab is active
+// moves it to another root window. If `confine` is set, the pointer cannot leave the screen of
+// `root`.
+fn grab_pointer(
+    conn: &Connection,
+    root: u32,
+    cursor: u32,
+    confine: bool,
+    timeout: Duration,
+) -> Result<()> {
+    let status = retry_grab(timeout, || {
+        let reply = xproto::grab_pointer(
+            conn,
+            false,
+            root,
+            GRAB_MASK,
+            xproto::GRAB_MODE_ASYNC as u8,
+            xproto::GRAB_MODE_ASYNC as u8,
+            if confine { root } else { xbase::NONE },
+            cursor,
+            xbase::CURRENT_TIME,
+        )
+        .get_reply()?;
+        Ok(reply.status())
+    })?;
+
+    if status != xproto::GRAB_STATUS_SUCCESS as u8 {
+        return Err(anyhow!(
+            "Could not grab pointer: {}",
+            grab_status_name(status)
+        ));
+    }
+
+    Ok(())
+}
+
+// Exclusively grabs the keyboard so the picker can be controlled with keys. Hotkey daemons hold
+// the keyboard until the hotkey that started us is released, so failing is not fatal: a warning
+// is printed and picking continues with the mouse only.
+fn grab_keyboard(conn: &Connection, root: u32, timeout: Duration) -> Result<()> {
+    let status = retry_grab(timeout, || {
+        let reply = xproto::grab_keyboard(
+            conn,
+            false,
+            root,
+            xbase::CURRENT_TIME,
+            xproto::GRAB_MODE_ASYNC as u8,
+            xproto::GRAB_MODE_ASYNC as u8,
+        )
+        .get_reply()?;
+        Ok(reply.status())
+    })?;
+
+    if status != xproto::GRAB_STATUS_SUCCESS as u8 {
+        eprintln!(
+            "warning: could not grab keyboard ({}), so only the mouse can be used",
+            grab_status_name(status)
+        );
     }
 
     Ok(())
@@ -43,36 +408,85 @@ fn update_cursor(conn: &Connection, cursor: u32) -> Result<()> {
     Ok(())
 }
 
-// Creates a new `XcursorImage`, draws the picker into it and loads it, returning the id for a `Cursor`
-fn create_new_xcursor(
-    conn: &Connection,
-    screenshot_pixels: &PixelSquare<&[ARGB]>,
-    preview_width: u32,
-) -> Result<u32> {
-    Ok(unsafe {
-        let mut cursor_image = XcursorImageCreate(preview_width as i32, preview_width as i32);
+// Pixels captured around the pointer, along with the label shown for them
+#[derive(PartialEq)]
+struct Capture {
+    width: u16,
+    pixels: Vec<ARGB>,
+    label: Option<(ARGB, String)>,
+}
 
-        // set the "hot spot" - this is where the pointer actually is inside the image
-        (*cursor_image).xhot = preview_width / 2;
-        (*cursor_image).yhot = preview_width / 2;
+impl Capture {
+    fn new(
+        conn: &Connection,
+        source: &Source,
+        preview: Preview,
+        point: (i16, i16),
+    ) -> Result<Capture> {
+        let (width, pixels) =
+            get_window_rect_around_pointer(conn, source, point, preview.width, preview.scale)?;
 
-        // get pixel data as a mutable Rust slice
-        let mut cursor_pixels =
-            PixelSquare::from_raw_parts((*cursor_image).pixels, preview_width as usize);
+        let label = match preview.label {
+            Some(formatter) => sample_preview(
+                &PixelSquare::new(&pixels[..], width.into()),
+                preview.sample_size,
+                preview.average,
+            )
+            .map(|color| (color.into(), formatter.format(color))),
+            None => None,
+        };
+
+        Ok(Capture {
+            width,
+            pixels: pixels.into_iter().map(preview_color).collect(),
+            label,
+        })
+    }
+
+    // Draws the magnifying glass showing the captured pixels into `target`
+    fn draw(&self, target: &mut PixelSquare<&mut [u32]>, preview: Preview) {
+        let screenshot_pixels = PixelSquare::new(&self.pixels[..], self.width.into());
 
         // find out how large our pixels should be in the picker - this must be an odd number (so
         // there's a center pixel) and it must be slightly higher than the ratio between the
-        // cursor and the screenshot (to account for integer division so no out of bounds accesses
+        // target and the screenshot (to account for integer division so no out of bounds accesses
         // occur when upscaling the image in `draw_magnifying_glass`)
-        let mut pixel_size = cursor_pixels.width() / screenshot_pixels.width();
-        if pixel_size % 2 == 0 {
+        let mut pixel_size = target.width() / screenshot_pixels.width();
+        if pixel_size.is_multiple_of(2) {
             pixel_size += 1;
         } else {
             pixel_size += 2;
         }
 
         // draw our custom image
-        draw_magnifying_glass(&mut cursor_pixels, screenshot_pixels, pixel_size);
+        draw_magnifying_glass(
+            target,
+            &screenshot_pixels,
+            pixel_size,
+            preview.sample_size as usize,
+            &preview.appearance,
+        );
+        if let Some((color, text)) = &self.label {
+            draw_label(target, &preview.appearance, *color, text);
+        }
+    }
+}
+
+// Creates a new `XcursorImage`, draws the picker into it and loads it, returning the id for a `Cursor`
+fn create_new_xcursor(conn: &Connection, capture: &Capture, preview: Preview) -> Result<u32> {
+    let preview_width = preview.width;
+    Ok(unsafe {
+        let cursor_image = XcursorImageCreate(preview_width as i32, preview_width as i32);
+
+        // set the "hot spot" - this is where the pointer actually is inside the image
+        (*cursor_image).xhot = preview_width / 2;
+        (*cursor_image).yhot = preview_width / 2;
+
+        // get pixel data as a mutable Rust slice
+        let mut cursor_pixels =
+            PixelSquare::from_raw_parts((*cursor_image).pixels, preview_width as usize);
+
+        capture.draw(&mut cursor_pixels, preview);
 
         // convert our XcursorImage into a cursor
         let cursor_id = XcursorImageLoadCursor(conn.get_raw_dpy(), cursor_image) as u32;
@@ -84,99 +498,560 @@ fn create_new_xcursor(
     } as u32)
 }
 
-// NOTE: this works for multi-monitor configurations since it seems that X fills in the blank
-// space with empty pixels when calling XGetImage with a rect that crosses the boundaries of two differently
-// sized or misaligned screens
+// Creates a crosshair cursor from the standard cursor font
+fn create_crosshair_cursor(conn: &Connection) -> Result<u32> {
+    let font = conn.generate_id();
+    xproto::open_font_checked(conn, font, "cursor").request_check()?;
+    let cursor = conn.generate_id();
+    xproto::create_glyph_cursor(
+        conn,
+        cursor,
+        font,
+        font,
+        CROSSHAIR_GLYPH,
+        CROSSHAIR_GLYPH + 1,
+        0,
+        0,
+        0,
+        0xffff,
+        0xffff,
+        0xffff,
+    );
+    xproto::close_font(conn, font);
+
+    Ok(cursor)
+}
+
+// Reads the pixels of the magnified area around the pointer. Pixels outside of the source, or
+// not shown on any monitor according to RandR, have no color.
 fn get_window_rect_around_pointer(
     conn: &Connection,
-    screen: &xproto::Screen,
-    (pointer_x, pointer_y): (i16, i16),
+    source: &Source,
+    pointer: (i16, i16),
     preview_width: u32,
     scale: u32,
-) -> Result<(u16, Vec<ARGB>)> {
-    let root = screen.root();
-    let root_width = screen.width_in_pixels() as isize;
-    let root_height = screen.height_in_pixels() as isize;
-
+) -> Result<(u16, Vec<Option<Color>>)> {
+    let (pointer_x, pointer_y) = source.to_local(pointer);
     let size = ((preview_width / scale) as isize).ensure_odd();
 
-    // the top left coordinates of the rect: make sure they don't go offscreen
-    let mut x = (pointer_x as isize) - (size / 2);
-    let mut y = (pointer_y as isize) - (size / 2);
-    let x_offset = if x < 0 { -x } else { 0 };
-    let y_offset = if y < 0 { -y } else { 0 };
-    x += x_offset;
-    y += y_offset;
-
-    // the size of the rect: make sure they don't extend past the screen
-    let size_x = if x + size > (root_width) {
-        (root_width) - x
-    } else {
-        size - x_offset
-    };
-    let size_y = if y + size > (root_height) {
-        (root_height) - y
-    } else {
-        size - y_offset
-    };
-
-    // grab a screenshot of the rect
-    let rect = (x as i16, y as i16, size_x as u16, size_y as u16);
-    let screenshot_rect = color::window_rect(conn, root, rect)?;
+    // the rect centered on the pointer, and the part of it that is inside the source
+    let x = (pointer_x as isize) - (size / 2);
+    let y = (pointer_y as isize) - (size / 2);
+    let rect = (x as i16, y as i16, size as u16, size as u16);
+    let clamped = source.clamp(rect);
 
     // the entire portion of the screenshot is on screen
-    if size_x == size && size_y == size {
-        return Ok((size as u16, screenshot_rect));
+    if clamped == Some(rect) {
+        return Ok((size as u16, source.read(conn, rect)?));
     }
 
-    // NOTE: XCB APIs fail when requesting a region outside the screen, so clamp the rect to the screen and
-    // fill the clamped pixels with empty data
-    let mut pixels = vec![ARGB::TRANSPARENT; (size * size) as usize];
-    for x in 0..size_x {
-        for y in 0..size_y {
-            let screenshot_idx = (y * size_x) + x;
-            let pixels_idx = (y + y_offset) * size + (x + x_offset);
+    // NOTE: XCB APIs fail when requesting a region outside the screen, so clamp the rect to the
+    // screen and leave the clamped pixels without color, just like the pixels between monitors
+    let mut pixels = vec![None; (size * size) as usize];
+    if let Some(clamped @ (clamped_x, clamped_y, size_x, size_y)) = clamped {
+        let screenshot_rect = source.read(conn, clamped)?;
+        let x_offset = clamped_x as isize - x;
+        let y_offset = clamped_y as isize - y;
+        let size_x = size_x as isize;
 
-            pixels[pixels_idx as usize] = screenshot_rect[screenshot_idx as usize];
+        for x in 0..size_x {
+            for y in 0..size_y as isize {
+                let screenshot_idx = (y * size_x) + x;
+                let pixels_idx = (y + y_offset) * size + (x + x_offset);
+
+                pixels[pixels_idx as usize] = screenshot_rect[screenshot_idx as usize];
+            }
         }
     }
 
     Ok((size as u16, pixels))
 }
 
-fn create_new_cursor(
+// Returns the next zoom level above `scale`, or `scale` if there is none
+fn zoom_in(scale: u32) -> u32 {
+    ZOOM_LEVELS
+        .iter()
+        .copied()
+        .find(|&level| level > scale)
+        .unwrap_or(scale)
+}
+
+// Returns the next zoom level below `scale`, or `scale` if there is none
+fn zoom_out(scale: u32) -> u32 {
+    ZOOM_LEVELS
+        .iter()
+        .rev()
+        .copied()
+        .find(|&level| level < scale)
+        .unwrap_or(scale)
+}
+
+// Moves the pointer by the given offset while keeping it on the screen. The server reports
+// warps as regular motion events, so the preview gets redrawn by the motion handler.
+fn move_pointer(
     conn: &Connection,
     screen: &xproto::Screen,
-    preview_width: u32,
-    scale: u32,
+    (pointer_x, pointer_y): (i16, i16),
+    (dx, dy): (isize, isize),
+) -> Result<()> {
+    let x = (pointer_x as isize + dx).clamp(0, screen.width_in_pixels() as isize - 1);
+    let y = (pointer_y as isize + dy).clamp(0, screen.height_in_pixels() as isize - 1);
+
+    xproto::warp_pointer_checked(
+        conn,
+        xbase::NONE,
+        screen.root(),
+        0,
+        0,
+        0,
+        0,
+        x as i16,
+        y as i16,
+    )
+    .request_check()?;
+
+    Ok(())
+}
+
+// Returns the part of the `size` by `size` area centered on `point` that is inside the source
+fn sample_rect(source: &Source, point: (i16, i16), size: u32) -> Option<(i16, i16, u16, u16)> {
+    let (x, y) = source.to_local(point);
+    let half = (size / 2) as i16;
+    source.clamp((x - half, y - half, size as u16, size as u16))
+}
+
+// Picks the combined color of the sample area around `point`, or `None` if the point is outside
+// the source or not shown on any monitor
+fn pick_color(
+    conn: &Connection,
+    source: &Source,
+    point: (i16, i16),
+    options: &PickerOptions,
+) -> Result<Option<Color>> {
+    match sample_rect(source, point, options.sample_size) {
+        Some(rect) => Ok(combine(&source.read(conn, rect)?, options.average)),
+        None => Ok(None),
+    }
+}
+
+// Picks the combined color of the area between two corners, or `None` if the area is outside the
+// source or not shown on any monitor
+fn pick_area(
+    conn: &Connection,
+    source: &Source,
+    from: (i16, i16),
+    to: (i16, i16),
+    average: Average,
+) -> Result<Option<Color>> {
+    let (x, y, width, height) = rect_between(source.to_local(from), source.to_local(to));
+    match source.clamp((x, y, width, height)) {
+        Some(rect) => Ok(combine(&source.read(conn, rect)?, average)),
+        None => Ok(None),
+    }
+}
+
+// Reports the color under the pointer to a watcher
+fn report_pointer(
+    conn: &Connection,
+    source: &Source,
+    point: (i16, i16),
+    options: &PickerOptions,
+    watch: &mut Watcher,
+) -> Result<()> {
+    if let Some(color) = pick_color(conn, source, point, options)? {
+        watch(source.to_local(point), color)?;
+    }
+    Ok(())
+}
+
+// Combines the sample area at the center of the captured pixels the same way picking does
+fn sample_preview(
+    pixels: &PixelSquare<&[Option<Color>]>,
+    sample_size: u32,
+    average: Average,
+) -> Option<Color> {
+    let center = pixels.width() / 2;
+    let half = (sample_size as usize / 2).min(center);
+    let range = center - half..=center + half;
+
+    let colors: Vec<Option<Color>> = range
+        .clone()
+        .flat_map(|x| range.clone().map(move |y| pixels[(x, y)]))
+        .collect();
+    combine(&colors, average)
+}
+
+// The color a pixel is shown with in the preview. Pixels are shown opaque, since the preview
+// can't show what is behind them, and pixels without contents are left transparent so that they
+// are drawn as stripes.
+fn preview_color(color: Option<Color>) -> ARGB {
+    match color {
+        Some(color) => ARGB {
+            a: 0xff,
+            ..color.into()
+        },
+        None => ARGB::TRANSPARENT,
+    }
+}
+
+// Combines the colors of the pixels that are shown on the screen, or returns `None` if there are
+// none
+fn combine(colors: &[Option<Color>], average: Average) -> Option<Color> {
+    let shown: Vec<Color> = colors.iter().flatten().copied().collect();
+
+    if shown.is_empty() {
+        None
+    } else {
+        Some(average.apply(&shown))
+    }
+}
+
+// Returns the position of the pointer, or `None` if it is on another screen
+fn pointer_position(conn: &Connection, screen: &xproto::Screen) -> Result<Option<(i16, i16)>> {
+    let pointer = xproto::query_pointer(conn, screen.root()).get_reply()?;
+    if !pointer.same_screen() {
+        return Ok(None);
+    }
+    Ok(Some((pointer.root_x(), pointer.root_y())))
+}
+
+// Returns the root window of the screen that an input event happened on
+fn event_root(event: &xbase::GenericEvent) -> Option<xproto::Window> {
+    match event.response_type() {
+        xproto::BUTTON_PRESS | xproto::BUTTON_RELEASE => {
+            let event: &xproto::ButtonPressEvent = unsafe { xbase::cast_event(event) };
+            Some(event.root())
+        }
+        xproto::KEY_PRESS | xproto::KEY_RELEASE => {
+            let event: &xproto::KeyPressEvent = unsafe { xbase::cast_event(event) };
+            Some(event.root())
+        }
+        xproto::MOTION_NOTIFY => {
+            let event: &xproto::MotionNotifyEvent = unsafe { xbase::cast_event(event) };
+            Some(event.root())
+        }
+        _ => None,
+    }
+}
+
+// How the magnifier is shown
+enum Display {
+    // drawn into the cursor, which gets replaced whenever the image changes
+    Cursor(u32),
+    // drawn into an overlay window next to the pointer, which shows a crosshair as cursor. While
+    // the overlay does not fit next to the pointer, the magnifier is drawn into a cursor instead.
+    Window(Overlay, u32, Option<u32>),
+}
+
+// The magnifier while picking is in progress
+struct Magnifier {
+    display: Display,
+    // the pixels that are currently shown, so unchanged images are not drawn again
+    drawn: Option<Capture>,
+    // the overlay is kept on the monitor showing the pointer
+    monitors: Vec<(i16, i16, u16, u16)>,
+}
+
+impl Magnifier {
+    // Creates the magnifier without drawing anything yet; until the first update, the cursor is a
+    // crosshair. The overlay window is used if the backend allows it, falling back to the cursor
+    // if the screen cannot show it.
+    fn new(
+        conn: &Connection,
+        screen: &xproto::Screen,
+        preview: Preview,
+        backend: Backend,
+    ) -> Result<Magnifier> {
+        let overlay = match backend {
+            Backend::Cursor => None,
+            Backend::Auto if !overlay::is_supported(conn, screen)? => None,
+            Backend::Auto | Backend::Window => Overlay::new(conn, screen, preview.width as u16)?,
+        };
+        let crosshair = create_crosshair_cursor(conn)?;
+        let display = match overlay {
+            Some(overlay) => Display::Window(overlay, crosshair, None),
+            None => Display::Cursor(crosshair),
+        };
+
+        Ok(Magnifier {
+            display,
+            drawn: None,
+            monitors: monitor::monitors(conn, screen)?,
+        })
+    }
+
+    // The cursor to show while the pointer is grabbed
+    fn cursor(&self) -> u32 {
+        match self.display {
+            Display::Cursor(cursor) => cursor,
+            Display::Window(_, crosshair, fallback) => fallback.unwrap_or(crosshair),
+        }
+    }
+
+    // Redraws the magnifier for the pixels around `point`. In cursor mode the pointer must
+    // already be grabbed.
+    fn update(
+        &mut self,
+        conn: &Connection,
+        screen: &xproto::Screen,
+        source: &Source,
+        preview: Preview,
+        point: (i16, i16),
+    ) -> Result<()> {
+        match &mut self.display {
+            Display::Cursor(cursor) => {
+                let capture = Capture::new(conn, source, preview, point)?;
+                if self.drawn.as_ref() == Some(&capture) {
+                    return Ok(());
+                }
+
+                let new_cursor = create_new_xcursor(conn, &capture, preview)?;
+                update_cursor(conn, new_cursor)?;
+
+                xproto::free_cursor(conn, *cursor);
+                *cursor = new_cursor;
+                self.drawn = Some(capture);
+            }
+            Display::Window(overlay, crosshair, fallback) => {
+                // everything captured or sampled around the pointer, which the overlay must not
+                // cover
+                let size = ((preview.width / preview.scale) as isize)
+                    .ensure_odd()
+                    .max(preview.sample_size as isize) as u16;
+                let half = (size / 2) as i16;
+                let captured = (point.0 - half, point.1 - half, size, size);
+
+                let bounds = monitor::containing(&self.monitors, point).unwrap_or((
+                    0,
+                    0,
+                    screen.width_in_pixels(),
+                    screen.height_in_pixels(),
+                ));
+                let gap = size / 2 + OVERLAY_MARGIN as u16;
+                let position = overlay::place(bounds, point, overlay.width(), gap);
+
+                // the overlay is moved out of the way before capturing, which avoids waiting for
+                // the screen to be repainted unless it has to be hidden
+                let was_shown = overlay.is_shown();
+                match position {
+                    Some(position) => overlay.show_at(conn, position),
+                    None => uncover_overlay(conn, overlay, source, captured)?,
+                }
+                let capture = Capture::new(conn, source, preview, point)?;
+
+                match position {
+                    Some(_) => {
+                        if let Some(cursor) = fallback.take() {
+                            update_cursor(conn, *crosshair)?;
+                            xproto::free_cursor(conn, cursor);
+                        }
+
+                        // moving the window keeps its contents, but unmapping it does not
+                        if !was_shown || self.drawn.as_ref() != Some(&capture) {
+                            let width = overlay.width() as usize;
+                            let mut pixels = vec![0; width * width];
+                            let mut pixels = PixelSquare::new(&mut pixels[..], width);
+                            capture.draw(&mut pixels, preview);
+                            overlay.draw(conn, &pixels);
+                            self.drawn = Some(capture);
+                        }
+                    }
+                    // the overlay does not fit next to the pointer, so the magnifier is shown in
+                    // the cursor until it does
+                    None => {
+                        overlay.hide(conn);
+                        if fallback.is_none() || self.drawn.as_ref() != Some(&capture) {
+                            let new_cursor = create_new_xcursor(conn, &capture, preview)?;
+                            update_cursor(conn, new_cursor)?;
+                            if let Some(cursor) = fallback.replace(new_cursor) {
+                                xproto::free_cursor(conn, cursor);
+                            }
+                            self.drawn = Some(capture);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Hides the magnifier if it covers any part of `rect`, which is in root coordinates
+    fn uncover(
+        &mut self,
+        conn: &Connection,
+        source: &Source,
+        rect: (i16, i16, u16, u16),
+    ) -> Result<()> {
+        if let Display::Window(overlay, _, _) = &mut self.display {
+            uncover_overlay(conn, overlay, source, rect)?;
+        }
+        Ok(())
+    }
+
+    fn free(self, conn: &Connection) {
+        match self.display {
+            Display::Cursor(cursor) => {
+                xproto::free_cursor(conn, cursor);
+            }
+            Display::Window(overlay, crosshair, fallback) => {
+                overlay.free(conn);
+                xproto::free_cursor(conn, crosshair);
+                if let Some(cursor) = fallback {
+                    xproto::free_cursor(conn, cursor);
+                }
+            }
+        }
+    }
+}
+
+// Hides the overlay if it covers any part of `rect`. Until whatever is below it is repainted, the
+// overlay can still be read from the screen, so reading is delayed until then.
+fn uncover_overlay(
+    conn: &Connection,
+    overlay: &mut Overlay,
+    source: &Source,
+    rect: (i16, i16, u16, u16),
+) -> Result<()> {
+    if overlay.uncover(conn, rect) && source.reads_screen() {
+        // the round trip makes sure the server has unmapped the window
+        xproto::get_input_focus(conn).get_reply()?;
+        std::thread::sleep(REPAINT_DELAY);
+    }
+    Ok(())
+}
+
+/// A screen that colors can be picked on, along with the source they are read from
+pub struct Target<'a> {
+    pub screen: xproto::Screen<'a>,
+    pub source: Source,
+}
+
+// The parts of a picking session that belong to the screen the pointer is on
+struct ActiveScreen {
+    index: usize,
+    outline: Outline,
+    magnifier: Magnifier,
+}
+
+impl ActiveScreen {
+    // Shows the picker on the screen of `targets[index]` and moves the grabs to its root window
+    fn new(
+        conn: &Connection,
+        targets: &[Target],
+        index: usize,
+        preview: Preview,
+        options: &PickerOptions,
+        confine: bool,
+    ) -> Result<ActiveScreen> {
+        let Target { screen, source } = &targets[index];
+        let outline = Outline::new(conn, screen)?;
+        let mut magnifier = Magnifier::new(conn, screen, preview, options.backend)?;
+
+        // grab the cursor to listen to all of its events
+        let timeout = options.grab_timeout;
+        grab_pointer(conn, screen.root(), magnifier.cursor(), confine, timeout)?;
+        grab_keyboard(conn, screen.root(), timeout)?;
+
+        // while the pointer is on another screen, the magnifier is drawn once it moves onto this
+        // one
+        if let Some(point) = pointer_position(conn, screen)? {
+            magnifier.update(conn, screen, source, preview, point)?;
+        }
+
+        Ok(ActiveScreen {
+            index,
+            outline,
+            magnifier,
+        })
+    }
+
+    fn root(&self, targets: &[Target]) -> xproto::Window {
+        targets[self.index].screen.root()
+    }
+
+    fn free(self, conn: &Connection) {
+        self.outline.free(conn);
+        self.magnifier.free(conn);
+    }
+}
+
+/// Picks the color at `point` without any user interaction. The point is given in source
+/// coordinates of the first target. If `point` is `None`, the color currently under the pointer
+/// is picked from the target on the screen the pointer is on.
+pub fn pick_location(
+    conn: &Connection,
+    targets: &[Target],
     point: Option<(i16, i16)>,
-) -> Result<u32> {
-    let point = match point {
-        Some(point) => point,
+    options: &PickerOptions,
+) -> Result<Color> {
+    let (source, point) = match point {
+        Some(point) => (&targets[0].source, targets[0].source.to_root(point)),
         None => {
-            let root = screen.root();
-            let pointer = xproto::query_pointer(conn, root).get_reply()?;
-            (pointer.root_x(), pointer.root_y())
+            let mut found = None;
+            for target in targets {
+                if let Some(pointer) = pointer_position(conn, &target.screen)? {
+                    found = Some((&target.source, pointer));
+                    break;
+                }
+            }
+            found
+                .ok_or_else(|| anyhow!("The pointer is not on a screen that can be picked from"))?
         }
     };
 
-    let (w, p) = get_window_rect_around_pointer(conn, screen, point, preview_width, scale)?;
-    let pixels = PixelSquare::new(&p[..], w.into());
-    create_new_xcursor(conn, &pixels, preview_width)
+    pick_color(conn, source, point, options)?.ok_or_else(|| {
+        let (x, y) = source.to_local(point);
+        anyhow!("Location {},{} is outside of the picked area", x, y)
+    })
 }
 
-pub fn wait_for_location(
+/// Reports the color under the pointer to `watch` until the selection button is clicked or
+/// `watch` returns an error. The pointer and keyboard are not grabbed and nothing is shown on the
+/// screen, so other clients keep receiving input; the pointer is read again every
+/// `WATCH_INTERVAL` instead.
+pub fn watch_pointer(
     conn: &Connection,
-    screen: &xproto::Screen,
-    preview_width: u32,
-    scale: u32,
-) -> Result<Option<ARGB>> {
-    let root = screen.root();
-    let preview_width = preview_width.ensure_odd();
+    targets: &[Target],
+    options: &PickerOptions,
+    mut watch: Watcher,
+) -> Result<()> {
+    // a button that is already held down when watching starts has to be released first
+    let mut was_pressed = true;
+    loop {
+        for target in targets {
+            let pointer = xproto::query_pointer(conn, target.screen.root()).get_reply()?;
+            if !pointer.same_screen() {
+                continue;
+            }
+
+            let pressed = pointer.mask() & xproto::BUTTON_MASK_1 as u16 != 0;
+            if pressed && !was_pressed {
+                return Ok(());
+            }
+            was_pressed = pressed;
+
+            let point = (pointer.root_x(), pointer.root_y());
+            report_pointer(conn, &target.source, point, options, &mut watch)?;
+            break;
+        }
+        std::thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+/// Lets the user choose a window on one of `screens` by clicking it. Returns the index of the
+/// screen and the window, or `None` if choosing was cancelled.
+pub fn select_window(
+    conn: &Connection,
+    screens: &[xproto::Screen],
+    grab_timeout: Duration,
+) -> Result<Option<(usize, xproto::Window)>> {
+    let root = screens[0].root();
+    let keymap = Keymap::new(conn)?;
+    let cursor = create_crosshair_cursor(conn)?;
 
-    // grab the cursor to listen to all of its events
-    let mut cursor = create_new_cursor(conn, screen, preview_width, scale, None)?;
-    grab_pointer(conn, root, cursor)?;
+    grab_pointer(conn, root, cursor, screens.len() == 1, grab_timeout)?;
+    grab_keyboard(conn, root, grab_timeout)?;
 
     let result = loop {
         let event = conn.wait_for_event();
@@ -184,43 +1059,316 @@ pub fn wait_for_location(
             match event.response_type() {
                 xproto::BUTTON_PRESS => {
                     let event: &xproto::ButtonPressEvent = unsafe { xbase::cast_event(&event) };
+                    let screen = match screens.iter().position(|s| s.root() == event.root()) {
+                        Some(screen) => screen,
+                        None => continue,
+                    };
                     match event.detail() {
-                        SELECTION_BUTTON => {
-                            let pixels = color::window_rect(
+                        // clicking the desktop picks from the root window
+                        SELECTION_BUTTON if event.child() == xbase::NONE => {
+                            break Ok(Some((screen, event.root())))
+                        }
+                        SELECTION_BUTTON => break Ok(Some((screen, event.child()))),
+                        CANCEL_BUTTON => break Ok(None),
+                        _ => {}
+                    }
+                }
+                xproto::KEY_PRESS => {
+                    let event: &xproto::KeyPressEvent = unsafe { xbase::cast_event(&event) };
+                    if keymap.keysym(event.detail()) == keysym::XK_Escape {
+                        break Ok(None);
+                    }
+                }
+                _ => {}
+            }
+        } else {
+            break Err(anyhow!("Lost connection to the X server"));
+        }
+    };
+
+    xproto::ungrab_keyboard(conn, xbase::CURRENT_TIME);
+    xproto::ungrab_pointer(conn, xbase::CURRENT_TIME);
+    xproto::free_cursor(conn, cursor);
+    conn.flush();
+
+    result
+}
+
+/// Waits for the user to pick colors, returning them in the order they were picked. Unless
+/// `options.multi` is set, the session ends after the first pick. An empty result means picking
+/// was cancelled.
+///
+/// The picker follows the pointer between the screens of `targets`. If there is only one
+/// target, the pointer is kept on its screen.
+pub fn wait_for_location(
+    conn: &Connection,
+    targets: &[Target],
+    options: &PickerOptions,
+) -> Result<Vec<Color>> {
+    let multi = options.multi;
+    let confine = targets.len() == 1;
+    let keymap = Keymap::new(conn)?;
+    let mut preview = Preview {
+        width: options.preview_width.ensure_odd(),
+        scale: options.scale,
+        sample_size: options.sample_size,
+        average: options.average,
+        label: options.label,
+        appearance: options.appearance,
+    };
+    let mut picks = Vec::new();
+    // where the selection button was pressed down
+    let mut drag_start = None;
+    // event that was read while skipping motion events, to be handled next
+    let mut pending = None;
+
+    // start on the screen the pointer is on
+    let mut start = 0;
+    for (index, target) in targets.iter().enumerate() {
+        if pointer_position(conn, &target.screen)?.is_some() {
+            start = index;
+            break;
+        }
+    }
+    // taken before grabbing, while no other events can arrive
+    let mut timer = if options.measure_latency {
+        Some(FrameTimer::new(conn, &targets[start].screen)?)
+    } else {
+        None
+    };
+    let mut active = ActiveScreen::new(conn, targets, start, preview, options, confine)?;
+
+    let result = loop {
+        let event = pending.take().or_else(|| conn.wait_for_event());
+        if let Some(event) = event {
+            let received = Instant::now();
+            // only the most recent pointer position needs to be drawn
+            let (event, skipped) = if event.response_type() == xproto::MOTION_NOTIFY {
+                let (event, skipped, next) = coalesce_motion(conn, event);
+                pending = next;
+                (event, skipped)
+            } else {
+                (event, 0)
+            };
+
+            // follow the pointer onto another screen
+            if let Some(root) = event_root(&event) {
+                if root != active.root(targets) {
+                    let index = match targets.iter().position(|t| t.screen.root() == root) {
+                        Some(index) => index,
+                        None => continue,
+                    };
+                    // an area cannot span several screens
+                    drag_start = None;
+                    active.free(conn);
+                    active = ActiveScreen::new(conn, targets, index, preview, options, confine)?;
+                }
+            }
+            let Target { screen, source } = &targets[active.index];
+
+            match event.response_type() {
+                xproto::BUTTON_PRESS => {
+                    let event: &xproto::ButtonPressEvent = unsafe { xbase::cast_event(&event) };
+                    match event.detail() {
+                        SELECTION_BUTTON => drag_start = Some((event.root_x(), event.root_y())),
+                        CANCEL_BUTTON if multi => break Ok(picks),
+                        CANCEL_BUTTON => break Ok(Vec::new()),
+                        ZOOM_IN_BUTTON | ZOOM_OUT_BUTTON => {
+                            preview.scale = if event.detail() == ZOOM_IN_BUTTON {
+                                zoom_in(preview.scale)
+                            } else {
+                                zoom_out(preview.scale)
+                            };
+                            active.magnifier.update(
                                 conn,
-                                root,
-                                (event.root_x(), event.root_y(), 1, 1),
+                                screen,
+                                source,
+                                preview,
+                                (event.root_x(), event.root_y()),
                             )?;
+                        }
+                        _ => {}
+                    }
+                }
+                xproto::BUTTON_RELEASE => {
+                    let event: &xproto::ButtonReleaseEvent = unsafe { xbase::cast_event(&event) };
+                    let start = match drag_start {
+                        Some(start) if event.detail() == SELECTION_BUTTON => start,
+                        _ => continue,
+                    };
+                    drag_start = None;
+
+                    // neither the outline nor the magnifier must end up in the picked pixels
+                    active.outline.clear(conn);
+                    let end = (event.root_x(), event.root_y());
+                    let color = if start == end {
+                        pick_color(conn, source, end, options)?
+                    } else {
+                        active
+                            .magnifier
+                            .uncover(conn, source, rect_between(start, end))?;
+                        pick_area(conn, source, start, end, options.average)?
+                    };
+                    if let Some(color) = color {
+                        picks.push(color);
+                        if !multi {
+                            break Ok(picks);
+                        }
+                    }
+                }
+                xproto::KEY_PRESS => {
+                    let event: &xproto::KeyPressEvent = unsafe { xbase::cast_event(&event) };
+                    let pointer = (event.root_x(), event.root_y());
 
-                            break Some(pixels[0]);
+                    // with shift held, arrow keys move the pointer by the width of the magnifier
+                    let step = if event.state() & xproto::MOD_MASK_SHIFT as u16 != 0 {
+                        ((preview.width / preview.scale) as isize).ensure_odd()
+                    } else {
+                        1
+                    };
+
+                    match keymap.keysym(event.detail()) {
+                        keysym::XK_Escape if multi => break Ok(picks),
+                        keysym::XK_Escape => break Ok(Vec::new()),
+                        keysym::XK_Return | keysym::XK_KP_Enter if multi => break Ok(picks),
+                        keysym::XK_Return | keysym::XK_KP_Enter | keysym::XK_space => {
+                            if let Some(color) = pick_color(conn, source, pointer, options)? {
+                                picks.push(color);
+                                if !multi {
+                                    break Ok(picks);
+                                }
+                            }
+                        }
+                        keysym::XK_BackSpace if multi => {
+                            picks.pop();
                         }
+                        keysym::XK_Left => move_pointer(conn, screen, pointer, (-step, 0))?,
+                        keysym::XK_Right => move_pointer(conn, screen, pointer, (step, 0))?,
+                        keysym::XK_Up => move_pointer(conn, screen, pointer, (0, -step))?,
+                        keysym::XK_Down => move_pointer(conn, screen, pointer, (0, step))?,
                         _ => {}
                     }
                 }
                 xproto::MOTION_NOTIFY => {
                     let event: &xproto::MotionNotifyEvent = unsafe { xbase::cast_event(&event) };
-                    let new_cursor = create_new_cursor(
-                        conn,
-                        screen,
-                        preview_width,
-                        scale,
-                        Some((event.root_x(), event.root_y())),
-                    )?;
-                    update_cursor(conn, new_cursor)?;
+                    let pointer = (event.root_x(), event.root_y());
 
-                    xproto::free_cursor(conn, cursor);
-                    cursor = new_cursor;
+                    // hide the outline while capturing so it does not show up in the pixels
+                    active.outline.clear(conn);
+                    active
+                        .magnifier
+                        .update(conn, screen, source, preview, pointer)?;
+                    if let Some(start) = drag_start {
+                        active.outline.draw(conn, start, pointer);
+                    }
+                    if let Some(timer) = &mut timer {
+                        timer.record(conn, event.time(), received, skipped)?;
+                    }
                 }
                 _ => {}
             }
         } else {
-            break None;
+            break Err(anyhow!("Lost connection to the X server"));
         }
     };
 
+    xproto::ungrab_keyboard(conn, xbase::CURRENT_TIME);
     xproto::ungrab_pointer(conn, xbase::CURRENT_TIME);
-    xproto::free_cursor(conn, cursor);
+    active.free(conn);
     conn.flush();
 
-    Ok(result)
+    if let Some(timer) = timer {
+        timer.summary();
+    }
+
+    result
+}
+
+#[test]
+fn test_zoom_levels() {
+    assert_eq!(zoom_in(8), 12);
+    assert_eq!(zoom_out(8), 6);
+    assert_eq!(zoom_in(5), 6);
+    assert_eq!(zoom_out(5), 4);
+    assert_eq!(zoom_in(64), 64);
+    assert_eq!(zoom_out(1), 1);
+    assert_eq!(zoom_out(100), 64);
+}
+
+#[test]
+fn test_retry_grab() {
+    let success = xproto::GRAB_STATUS_SUCCESS as u8;
+    let grabbed = xproto::GRAB_STATUS_ALREADY_GRABBED as u8;
+
+    // the other client releases its grab after two attempts
+    let mut attempts = 0;
+    let status = retry_grab(Duration::from_secs(10), || {
+        attempts += 1;
+        Ok(if attempts > 2 { success } else { grabbed })
+    });
+    assert_eq!((status.unwrap(), attempts), (success, 3));
+
+    // a timeout too long to represent does not overflow
+    let mut attempts = 0;
+    let status = retry_grab(Duration::MAX, || {
+        attempts += 1;
+        Ok(if attempts > 2 { success } else { grabbed })
+    });
+    assert_eq!((status.unwrap(), attempts), (success, 3));
+
+    // without a timeout there is only one attempt
+    let mut attempts = 0;
+    let status = retry_grab(Duration::ZERO, || {
+        attempts += 1;
+        Ok(grabbed)
+    });
+    assert_eq!((status.unwrap(), attempts), (grabbed, 1));
+
+    assert_eq!(grab_status_name(grabbed), "AlreadyGrabbed");
+    assert_eq!(grab_status_name(xproto::GRAB_STATUS_FROZEN as u8), "Frozen");
+    assert_eq!(
+        grab_status_name(xproto::GRAB_STATUS_NOT_VIEWABLE as u8),
+        "NotViewable"
+    );
+}
+
+#[test]
+fn test_rect_between() {
+    assert_eq!(rect_between((5, 5), (5, 5)), (5, 5, 1, 1));
+    assert_eq!(rect_between((2, 3), (4, 8)), (2, 3, 3, 6));
+    assert_eq!(rect_between((4, 8), (2, 3)), (2, 3, 3, 6));
+    assert_eq!(rect_between((0, 10), (10, 0)), (0, 0, 11, 11));
+}
+
+#[test]
+fn test_sample_preview() {
+    let c = Color::from(ARGB::new(0xff, 10, 20, 30));
+    let pixels = [
+        None,
+        Some(c),
+        Some(ARGB::WHITE.into()),
+        None,
+        Some(c),
+        Some(c),
+        None,
+        Some(c),
+        Some(ARGB::BLACK.into()),
+    ];
+    let pixels = PixelSquare::new(&pixels[..], 3);
+
+    let center = sample_preview(&pixels, 1, Average::Median).unwrap();
+    assert!(center == c);
+
+    let block = sample_preview(&pixels, 3, Average::Median).unwrap();
+    assert!(block == c);
+
+    let offscreen = [None];
+    let offscreen = PixelSquare::new(&offscreen[..], 1);
+    assert!(sample_preview(&offscreen, 1, Average::Mean).is_none());
+
+    // transparent pixels are picked like any other
+    let transparent = [Some(Color::from(ARGB::TRANSPARENT))];
+    let transparent = PixelSquare::new(&transparent[..], 1);
+    let picked = sample_preview(&transparent, 1, Average::Mean).unwrap();
+    assert!(picked == Color::from(ARGB::TRANSPARENT));
 }
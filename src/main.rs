@@ -2,26 +2,51 @@ mod atoms;
 mod cli;
 mod color;
 mod draw;
+mod font;
 mod format;
+mod keyboard;
 mod location;
+mod monitor;
+mod names;
+mod overlay;
 mod pixel;
 mod selection;
+mod shm;
+mod source;
+mod space;
 mod util;
+mod visual;
+
+use std::io::{self, Write};
+use std::time::Duration;
 
 use anyhow::{anyhow, Result};
 use clap::{value_t, ArgMatches, ErrorKind};
 use nix::unistd::ForkResult;
 use xcb::base::Connection;
+use xcb::xproto;
 
 use crate::cli::get_cli;
+use crate::color::{Average, Color, ARGB};
+use crate::draw::{Appearance, Marker, Shape};
 use crate::format::{Format, FormatColor, FormatString};
-use crate::location::wait_for_location;
+use crate::location::{
+    pick_location, select_window, wait_for_location, watch_pointer, Backend, PickerOptions, Target,
+};
 use crate::selection::{into_daemon, set_selection, Selection};
+use crate::source::Source;
+use crate::util::{json_string, parse_duration, parse_id, parse_point, EnsureOdd};
 
 const DEFAULT_PREVIEW_SIZE: u32 = 256 - 1;
 const DEFAULT_SCALE: u32 = 8;
+const DEFAULT_SAMPLE_SIZE: u32 = 1;
+const DEFAULT_BORDER_WIDTH: usize = 1;
+
+// Exit status used when picking is cancelled by the user
+const EXIT_CANCELLED: i32 = 2;
 
-fn run(args: &ArgMatches) -> Result<()> {
+// Returns `false` if the user cancelled picking
+fn run(args: &ArgMatches) -> Result<bool> {
     fn error(message: &str) -> ! {
         clap::Error::with_description(message, clap::ErrorKind::InvalidValue).exit()
     }
@@ -51,28 +76,227 @@ fn run(args: &ArgMatches) -> Result<()> {
             ErrorKind::ArgumentNotFound => DEFAULT_PREVIEW_SIZE,
             _ => error(&format!("{}", e)),
         });
+    let sample_size =
+        value_t!(args.value_of("sample_size"), u32).unwrap_or_else(|e| match e.kind {
+            ErrorKind::ArgumentNotFound => DEFAULT_SAMPLE_SIZE,
+            _ => error(&format!("{}", e)),
+        });
+    // an even size has no center pixel, which also rules out 0
+    if sample_size % 2 == 0 {
+        error("Sample size must be odd");
+    }
 
     let selection = args.values_of("selection").and_then(|mut v| {
         v.next()
             .map_or(Some(Selection::Clipboard), |v| v.parse::<Selection>().ok())
     });
+    let multi = args.is_present("multi");
+    let at = args
+        .value_of("at")
+        .map(|at| parse_point(at).unwrap_or_else(|| error("Invalid coordinates")));
+    let at_pointer = args.is_present("at_pointer");
+    let window = args
+        .value_of("window")
+        .map(|window| parse_id(window).unwrap_or_else(|| error("Invalid window id")));
+    let screen_number = match value_t!(args.value_of("screen"), usize) {
+        Ok(number) => Some(number),
+        Err(e) if e.kind == ErrorKind::ArgumentNotFound => None,
+        Err(e) => error(&format!("{}", e)),
+    };
+    let choose_window = args.is_present("select_window");
+    let watch = args.is_present("watch_pointer");
+    let json = args.is_present("json");
+    let label = args.is_present("label");
+    let freeze = args.is_present("freeze");
+    let shm = !args.is_present("no_shm");
+    let average = args
+        .value_of("average")
+        .unwrap_or("mean")
+        .parse::<Average>()
+        .unwrap_or_else(|e| error(&format!("{}", e)));
+    let backend = args
+        .value_of("backend")
+        .unwrap_or("auto")
+        .parse::<Backend>()
+        .unwrap_or_else(|e| error(&format!("{}", e)));
+    let grab_timeout = args
+        .value_of("grab_timeout")
+        .map_or(Duration::ZERO, |timeout| {
+            parse_duration(timeout).unwrap_or_else(|| error("Invalid grab timeout"))
+        });
+    let color_arg = |name| {
+        args.value_of(name).map(|color| {
+            color
+                .parse::<ARGB>()
+                .unwrap_or_else(|e| error(&format!("{}", e)))
+        })
+    };
+    let appearance = Appearance {
+        shape: args
+            .value_of("shape")
+            .unwrap_or("circle")
+            .parse::<Shape>()
+            .unwrap_or_else(|e| error(&format!("{}", e))),
+        grid: !args.is_present("no_grid"),
+        border_width: value_t!(args.value_of("border_width"), usize).unwrap_or_else(|e| {
+            match e.kind {
+                ErrorKind::ArgumentNotFound => DEFAULT_BORDER_WIDTH,
+                _ => error(&format!("{}", e)),
+            }
+        }),
+        marker: args
+            .value_of("marker")
+            .unwrap_or("box")
+            .parse::<Marker>()
+            .unwrap_or_else(|e| error(&format!("{}", e))),
+        border_color: color_arg("border_color"),
+        grid_color: color_arg("grid_color"),
+        marker_color: color_arg("marker_color"),
+    };
     let use_selection = selection.is_some();
     let background = std::env::var("XCOLOR_FOREGROUND").is_err();
 
     let mut in_parent = true;
+    let picked;
 
-    let (conn, screen) = Connection::connect_with_xlib_display()?;
+    let (conn, default_screen) = Connection::connect_with_xlib_display()?;
 
     {
-        let screen = conn
-            .get_setup()
+        let setup = conn.get_setup();
+        let root = setup
             .roots()
-            .nth(screen as usize)
-            .ok_or_else(|| anyhow!("Could not find screen"))?;
-        let root = screen.root();
+            .nth(default_screen as usize)
+            .ok_or_else(|| anyhow!("Could not find screen"))?
+            .root();
+
+        // the screens to pick from; coordinates given with --at on the root window refer to the
+        // default screen
+        let from_root = window.is_none() && !choose_window;
+        let screens: Vec<_> = match screen_number {
+            Some(number) => vec![setup
+                .roots()
+                .nth(number)
+                .ok_or_else(|| anyhow!("Could not find screen {}", number))?],
+            None if at.is_some() && from_root => setup
+                .roots()
+                .skip(default_screen as usize)
+                .take(1)
+                .collect(),
+            None => setup.roots().collect(),
+        };
+        // no larger area can be sampled, and larger sizes would not fit into coordinates
+        let largest = screens
+            .iter()
+            .map(|screen| screen.width_in_pixels().max(screen.height_in_pixels()))
+            .max()
+            .unwrap_or(1);
+        let sample_size = sample_size.min((largest as u32).ensure_odd());
+
+        let options = PickerOptions {
+            preview_width: preview_size,
+            scale,
+            multi,
+            average,
+            sample_size,
+            label: if label { Some(formatter) } else { None },
+            backend,
+            measure_latency: std::env::var_os("XCOLOR_LATENCY").is_some(),
+            grab_timeout,
+            appearance,
+        };
+        let mut targets = Vec::new();
+        if choose_window {
+            if let Some((index, window)) = select_window(&conn, &screens, grab_timeout)? {
+                let source = Source::window(&conn, &screens[index], window)?;
+                targets.push(Target {
+                    screen: screens.into_iter().nth(index).unwrap(),
+                    source,
+                });
+            }
+        } else if let Some(window) = window {
+            let window_root = xproto::get_geometry(&conn, window)
+                .get_reply()
+                .map_err(|_| anyhow!("Could not find window {:#x}", window))?
+                .root();
+            let screen = screens
+                .into_iter()
+                .find(|screen| screen.root() == window_root)
+                .ok_or_else(|| anyhow!("Window {:#x} is not on the picked screen", window))?;
+            let source = Source::window(&conn, &screen, window)?;
+            targets.push(Target { screen, source });
+        } else {
+            for screen in screens {
+                let source = Source::root(&conn, &screen)?;
+                targets.push(Target { screen, source });
+            }
+        }
+
+        for Target { source, .. } in &mut targets {
+            // snapshot the source before anything of ours is shown on the screen. Everything is
+            // read from the snapshot afterwards, so there is no need to share memory.
+            if freeze {
+                source.freeze(&conn)?;
+            } else if shm {
+                // the largest images read are the pixels shown in the magnifier when it is zoomed
+                // out all the way, and the sampled pixels
+                let size = preview_size.ensure_odd().max(sample_size);
+                source.share_memory(&conn, size)?;
+            }
+        }
+
+        // prints the color under the pointer whenever it changes
+        let mut last_color = None;
+        let mut report = |(x, y): (i16, i16), color: Color| -> Result<()> {
+            if last_color == Some(color) {
+                return Ok(());
+            }
+            last_color = Some(color);
 
-        if let Some(color) = wait_for_location(&conn, &screen, preview_size, scale)? {
             let output = formatter.format(color);
+            if json {
+                let output = json_string(&output);
+                writeln!(
+                    io::stdout(),
+                    "{{\"x\":{},\"y\":{},\"color\":{}}}",
+                    x,
+                    y,
+                    output
+                )?;
+            } else {
+                writeln!(io::stdout(), "{}", output)?;
+            }
+            Ok(())
+        };
+
+        let colors = if targets.is_empty() {
+            // choosing the window was cancelled
+            Vec::new()
+        } else if at.is_some() || at_pointer {
+            vec![pick_location(&conn, &targets, at, &options)?]
+        } else if watch {
+            // watching also ends when whatever reads the output goes away
+            match watch_pointer(&conn, &targets, &options, &mut report) {
+                Err(err) if is_broken_pipe(&err) => Vec::new(),
+                Err(err) => return Err(err),
+                Ok(()) => Vec::new(),
+            }
+        } else {
+            wait_for_location(&conn, &targets, &options)?
+        };
+
+        // colors have already been printed while watching
+        picked = !colors.is_empty() || (watch && !targets.is_empty());
+
+        for target in targets {
+            target.source.free(&conn);
+        }
+
+        if !colors.is_empty() {
+            let output = colors
+                .into_iter()
+                .map(|color| formatter.format(color))
+                .collect::<Vec<_>>()
+                .join("\n");
 
             if use_selection {
                 if background {
@@ -95,13 +319,23 @@ fn run(args: &ArgMatches) -> Result<()> {
         std::mem::forget(conn);
     }
 
-    Ok(())
+    Ok(picked)
+}
+
+// Whether an error comes from writing to an output that has been closed
+fn is_broken_pipe(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<io::Error>()
+        .is_some_and(|err| err.kind() == io::ErrorKind::BrokenPipe)
 }
 
 fn main() {
     let args = get_cli().get_matches();
-    if let Err(err) = run(&args) {
-        eprintln!("error: {}", err);
-        std::process::exit(1);
+    match run(&args) {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(EXIT_CANCELLED),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
     }
 }
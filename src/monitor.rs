@@ -0,0 +1,199 @@
+use std::ptr;
+
+use anyhow::{anyhow, Result};
+use xcb::base::Connection;
+use xcb::ffi::base::{xcb_connection_t, xcb_generic_error_t};
+use xcb::{randr, xproto};
+
+/// A rectangle given by its top left corner, width and height
+pub type Rect = (i16, i16, u16, u16);
+
+// GetMonitors was added in RandR 1.5, which is newer than the protocol descriptions the xcb crate
+// is generated from, so it is called through libxcb-randr directly
+#[repr(C)]
+struct GetMonitorsCookie {
+    sequence: libc::c_uint,
+}
+
+#[repr(C)]
+struct GetMonitorsReply {
+    response_type: u8,
+    pad0: u8,
+    sequence: u16,
+    length: u32,
+    timestamp: xproto::Timestamp,
+    n_monitors: u32,
+    n_outputs: u32,
+    pad1: [u8; 12],
+}
+
+#[repr(C)]
+struct MonitorInfo {
+    name: xproto::Atom,
+    primary: u8,
+    automatic: u8,
+    n_output: u16,
+    x: i16,
+    y: i16,
+    width: u16,
+    height: u16,
+    width_in_millimeters: u32,
+    height_in_millimeters: u32,
+}
+
+#[repr(C)]
+struct MonitorInfoIterator {
+    data: *mut MonitorInfo,
+    rem: libc::c_int,
+    index: libc::c_int,
+}
+
+#[link(name = "xcb-randr")]
+extern "C" {
+    fn xcb_randr_get_monitors(
+        c: *mut xcb_connection_t,
+        window: xproto::Window,
+        get_active: u8,
+    ) -> GetMonitorsCookie;
+    fn xcb_randr_get_monitors_reply(
+        c: *mut xcb_connection_t,
+        cookie: GetMonitorsCookie,
+        e: *mut *mut xcb_generic_error_t,
+    ) -> *mut GetMonitorsReply;
+    fn xcb_randr_get_monitors_monitors_iterator(r: *const GetMonitorsReply) -> MonitorInfoIterator;
+    fn xcb_randr_monitor_info_next(i: *mut MonitorInfoIterator);
+}
+
+/// Returns the areas of the root window that are shown on a monitor, in root window coordinates.
+/// Without RandR the whole screen is assumed to be shown.
+///
+/// The areas are the monitors RandR reports, which can span several outputs. RandR versions
+/// before 1.5 know no monitors, so the CRTCs driving the outputs are used instead. Either way the
+/// geometry is reported after rotation, reflection and transforms are applied, so it matches the
+/// root window coordinates that the pointer moves in and that images are read from, regardless
+/// of how the output is turned.
+pub fn monitors(conn: &Connection, screen: &xproto::Screen) -> Result<Vec<Rect>> {
+    let whole = vec![(0, 0, screen.width_in_pixels(), screen.height_in_pixels())];
+
+    let present = conn
+        .get_extension_data(randr::id())
+        .is_some_and(|ext| ext.present());
+    if !present {
+        return Ok(whole);
+    }
+
+    let version = randr::query_version(conn, 1, 5).get_reply()?;
+    let version = (version.major_version(), version.minor_version());
+    let rects = if version >= (1, 5) {
+        active_monitors(conn, screen)?
+    } else if version >= (1, 3) {
+        // GetScreenResourcesCurrent was added in version 1.3
+        crtcs(conn, screen)?
+    } else {
+        return Ok(whole);
+    };
+
+    // some drivers report no monitors or CRTCs at all, for example in virtual machines
+    if rects.is_empty() {
+        return Ok(whole);
+    }
+    Ok(rects)
+}
+
+// Returns the areas of the monitors that are turned on
+fn active_monitors(conn: &Connection, screen: &xproto::Screen) -> Result<Vec<Rect>> {
+    let mut rects = Vec::new();
+    unsafe {
+        let raw = conn.get_raw_conn();
+        let cookie = xcb_randr_get_monitors(raw, screen.root(), 1);
+        let mut error = ptr::null_mut();
+        let reply = xcb_randr_get_monitors_reply(raw, cookie, &mut error);
+        if reply.is_null() {
+            libc::free(error as *mut libc::c_void);
+            return Err(anyhow!("Could not get the monitors of the screen"));
+        }
+
+        let mut monitors = xcb_randr_get_monitors_monitors_iterator(reply);
+        while monitors.rem > 0 {
+            let info = &*monitors.data;
+            if info.width > 0 && info.height > 0 {
+                rects.push((info.x, info.y, info.width, info.height));
+            }
+            xcb_randr_monitor_info_next(&mut monitors);
+        }
+        libc::free(reply as *mut libc::c_void);
+    }
+    Ok(rects)
+}
+
+// Returns the areas of the CRTCs that are turned on
+fn crtcs(conn: &Connection, screen: &xproto::Screen) -> Result<Vec<Rect>> {
+    let resources = randr::get_screen_resources_current(conn, screen.root()).get_reply()?;
+    let cookies: Vec<_> = resources
+        .crtcs()
+        .iter()
+        .map(|&crtc| randr::get_crtc_info(conn, crtc, resources.config_timestamp()))
+        .collect();
+
+    let mut rects = Vec::new();
+    for cookie in cookies {
+        let info = cookie.get_reply()?;
+        // CRTCs without a mode are turned off
+        if info.mode() != 0 && info.width() > 0 && info.height() > 0 {
+            rects.push((info.x(), info.y(), info.width(), info.height()));
+        }
+    }
+    Ok(rects)
+}
+
+/// Returns the monitor showing `point`, if any
+pub fn containing(monitors: &[Rect], point: (i16, i16)) -> Option<Rect> {
+    monitors.iter().copied().find(|&rect| contains(rect, point))
+}
+
+/// Returns whether two rectangles share any pixels
+pub fn overlaps((x0, y0, w0, h0): Rect, (x1, y1, w1, h1): Rect) -> bool {
+    let (x0, y0, x1, y1) = (x0 as i32, y0 as i32, x1 as i32, y1 as i32);
+    x0 < x1 + w1 as i32 && x1 < x0 + w0 as i32 && y0 < y1 + h1 as i32 && y1 < y0 + h0 as i32
+}
+
+// Whether `point` lies inside `rect`
+fn contains((x, y, width, height): Rect, (px, py): (i16, i16)) -> bool {
+    let (px, py) = (px as i32, py as i32);
+    px >= x as i32
+        && px < x as i32 + width as i32
+        && py >= y as i32
+        && py < y as i32 + height as i32
+}
+
+#[test]
+fn test_containing() {
+    // a landscape monitor next to a taller, rotated one
+    let monitors = [(0, 0, 1920, 1080), (1920, 0, 1080, 1920)];
+
+    assert_eq!(containing(&monitors, (0, 0)), Some(monitors[0]));
+    assert_eq!(containing(&monitors, (1919, 1079)), Some(monitors[0]));
+    assert_eq!(containing(&monitors, (1920, 1079)), Some(monitors[1]));
+    assert_eq!(containing(&monitors, (2999, 1919)), Some(monitors[1]));
+    // the gap below the smaller monitor
+    assert_eq!(containing(&monitors, (100, 1080)), None);
+    assert_eq!(containing(&monitors, (3000, 0)), None);
+    assert_eq!(containing(&monitors, (-1, 0)), None);
+}
+
+#[test]
+fn test_overlaps() {
+    let rect = (10, 10, 20, 20);
+    assert!(overlaps(rect, rect));
+    assert!(overlaps(rect, (0, 0, 11, 11)));
+    assert!(overlaps(rect, (29, 29, 5, 5)));
+    assert!(overlaps(rect, (15, 0, 1, 100)));
+    assert!(!overlaps(rect, (0, 0, 10, 10)));
+    assert!(!overlaps(rect, (30, 10, 5, 5)));
+    assert!(!overlaps(rect, (10, -5, 20, 15)));
+
+    assert!(overlaps((0, 0, 10, 10), (5, 5, 10, 10)));
+    assert!(overlaps((0, 0, 10, 10), (2, 2, 1, 1)));
+    assert!(!overlaps((0, 0, 10, 10), (10, 0, 5, 5)));
+    assert!(!overlaps((0, 0, 10, 10), (-5, -5, 5, 20)));
+}
@@ -0,0 +1,238 @@
+use anyhow::Result;
+use xcb::base as xbase;
+use xcb::base::Connection;
+use xcb::xproto;
+
+use crate::monitor::{overlaps, Rect};
+use crate::pixel::PixelSquare;
+
+/// Override-redirect window with an ARGB visual that shows the magnifier next to the pointer.
+/// The window is never placed over the captured area, so it does not end up in its own preview.
+pub struct Overlay {
+    window: xproto::Window,
+    colormap: xproto::Colormap,
+    gc: xproto::Gcontext,
+    width: u16,
+    // where the window is shown, or `None` while it is unmapped
+    position: Option<(i16, i16)>,
+}
+
+impl Overlay {
+    /// Creates an unmapped overlay of `width` by `width` pixels. Returns `None` if the screen has
+    /// no 32-bit visual to draw a translucent window with.
+    pub fn new(conn: &Connection, screen: &xproto::Screen, width: u16) -> Result<Option<Overlay>> {
+        let visual = match argb_visual(screen) {
+            Some(visual) => visual,
+            None => return Ok(None),
+        };
+
+        let colormap = conn.generate_id();
+        xproto::create_colormap(
+            conn,
+            xproto::COLORMAP_ALLOC_NONE as u8,
+            colormap,
+            screen.root(),
+            visual,
+        );
+
+        let window = conn.generate_id();
+        xproto::create_window_checked(
+            conn,
+            32,
+            window,
+            screen.root(),
+            0,
+            0,
+            width,
+            width,
+            0,
+            xproto::WINDOW_CLASS_INPUT_OUTPUT as u16,
+            visual,
+            &[
+                (xproto::CW_BACK_PIXEL, 0),
+                (xproto::CW_BORDER_PIXEL, 0),
+                (xproto::CW_OVERRIDE_REDIRECT, 1),
+                (xproto::CW_COLORMAP, colormap),
+            ],
+        )
+        .request_check()?;
+
+        let gc = conn.generate_id();
+        xproto::create_gc(conn, gc, window, &[]);
+
+        Ok(Some(Overlay {
+            window,
+            colormap,
+            gc,
+            width,
+            position: None,
+        }))
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    /// Replaces the contents of the window with `pixels`, which must be `width` pixels wide
+    pub fn draw(&self, conn: &Connection, pixels: &PixelSquare<&mut [u32]>) {
+        // image data has to be sent in the byte order of the server
+        let lsb_first = conn.get_setup().image_byte_order() == xproto::IMAGE_ORDER_LSB_FIRST as u8;
+        let data: Vec<u8> = (0..pixels.width() * pixels.width())
+            .flat_map(|idx| {
+                if lsb_first {
+                    pixels[idx].to_le_bytes()
+                } else {
+                    pixels[idx].to_be_bytes()
+                }
+            })
+            .collect();
+
+        xproto::put_image(
+            conn,
+            xproto::IMAGE_FORMAT_Z_PIXMAP as u8,
+            self.window,
+            self.gc,
+            self.width,
+            self.width,
+            0,
+            0,
+            0,
+            32,
+            &data,
+        );
+    }
+
+    /// Moves the window to `(x, y)` and raises it above all other windows
+    pub fn show_at(&mut self, conn: &Connection, (x, y): (i16, i16)) {
+        xproto::configure_window(
+            conn,
+            self.window,
+            &[
+                (xproto::CONFIG_WINDOW_X as u16, x as u32),
+                (xproto::CONFIG_WINDOW_Y as u16, y as u32),
+                (
+                    xproto::CONFIG_WINDOW_STACK_MODE as u16,
+                    xproto::STACK_MODE_ABOVE,
+                ),
+            ],
+        );
+        if self.position.is_none() {
+            xproto::map_window(conn, self.window);
+        }
+        self.position = Some((x, y));
+    }
+
+    pub fn is_shown(&self) -> bool {
+        self.position.is_some()
+    }
+
+    pub fn hide(&mut self, conn: &Connection) {
+        if self.position.take().is_some() {
+            xproto::unmap_window(conn, self.window);
+        }
+    }
+
+    /// Hides the window if it is shown over any part of `rect`, which is in root coordinates.
+    /// Returns whether it was hidden.
+    pub fn uncover(&mut self, conn: &Connection, rect: Rect) -> bool {
+        match self.position {
+            Some((x, y)) if overlaps((x, y, self.width, self.width), rect) => {
+                self.hide(conn);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn free(self, conn: &Connection) {
+        xproto::destroy_window(conn, self.window);
+        xproto::free_gc(conn, self.gc);
+        xproto::free_colormap(conn, self.colormap);
+    }
+}
+
+// Finds a 32-bit TrueColor visual, whose extra 8 bits are used as alpha channel by compositing
+// managers
+fn argb_visual(screen: &xproto::Screen) -> Option<xproto::Visualid> {
+    screen
+        .allowed_depths()
+        .filter(|depth| depth.depth() == 32)
+        .flat_map(|depth| depth.visuals())
+        .find(|visual| visual.class() == xproto::VISUAL_CLASS_TRUE_COLOR as u8)
+        .map(|visual| visual.visual_id())
+}
+
+/// Returns whether an overlay can be drawn with transparent corners, which requires both a 32-bit
+/// visual and a running compositing manager
+pub fn is_supported(conn: &Connection, screen: &xproto::Screen) -> Result<bool> {
+    if argb_visual(screen).is_none() {
+        return Ok(false);
+    }
+
+    // compositing managers own the `_NET_WM_CM_Sn` selection of the screen they manage
+    let number = conn
+        .get_setup()
+        .roots()
+        .position(|other| other.root() == screen.root())
+        .unwrap_or(0);
+    let name = format!("_NET_WM_CM_S{}", number);
+    let atom = xproto::intern_atom(conn, false, &name).get_reply()?.atom();
+    let owner = xproto::get_selection_owner(conn, atom).get_reply()?.owner();
+
+    Ok(owner != xbase::NONE)
+}
+
+/// Finds a spot for a `width` by `width` overlay next to `pointer` that keeps it inside `bounds`
+/// and at least `gap` pixels away from the pointer on one axis. Spots diagonally next to the
+/// pointer, which keep that distance on both axes, are preferred. Returns `None` if there is no
+/// such spot.
+pub fn place(bounds: Rect, (x, y): (i16, i16), width: u16, gap: u16) -> Option<(i16, i16)> {
+    let (bx, by, bw, bh) = bounds;
+    let (x, y, width, gap) = (x as i32, y as i32, width as i32, gap as i32);
+    let (x0, y0) = (bx as i32, by as i32);
+    let (x1, y1) = (x0 + bw as i32, y0 + bh as i32);
+
+    let after = |pos: i32| pos + gap;
+    let before = |pos: i32| pos - gap - width;
+    // centered on the pointer, but moved inside the bounds
+    let center_x = (x - width / 2).min(x1 - width).max(x0);
+    let center_y = (y - width / 2).min(y1 - width).max(y0);
+
+    // prefer the bottom right, as is usual for tooltips
+    [
+        (after(x), after(y)),
+        (before(x), after(y)),
+        (after(x), before(y)),
+        (before(x), before(y)),
+        (after(x), center_y),
+        (before(x), center_y),
+        (center_x, after(y)),
+        (center_x, before(y)),
+    ]
+    .iter()
+    .find(|&&(ox, oy)| ox >= x0 && oy >= y0 && ox + width <= x1 && oy + width <= y1)
+    .map(|&(ox, oy)| (ox as i16, oy as i16))
+}
+
+#[test]
+fn test_place() {
+    let bounds = (0, 0, 1000, 800);
+
+    assert_eq!(place(bounds, (100, 100), 200, 20), Some((120, 120)));
+    assert_eq!(place(bounds, (900, 100), 200, 20), Some((680, 120)));
+    assert_eq!(place(bounds, (100, 700), 200, 20), Some((120, 480)));
+    assert_eq!(place(bounds, (990, 790), 200, 20), Some((770, 570)));
+    assert_eq!(
+        place((0, 0, 1000, 300), (100, 150), 200, 20),
+        Some((120, 50))
+    );
+    assert_eq!(
+        place((0, 0, 1000, 300), (900, 150), 200, 20),
+        Some((680, 50))
+    );
+    assert_eq!(
+        place((0, 0, 300, 1000), (150, 100), 200, 20),
+        Some((50, 120))
+    );
+    assert_eq!(place((0, 0, 300, 300), (150, 150), 200, 20), None);
+}
@@ -0,0 +1,126 @@
+use std::{ptr, slice};
+
+use anyhow::Result;
+use xcb::base::Connection;
+use xcb::{shm, xproto};
+
+use crate::visual::ImageLayout;
+
+/// Shared memory segment that the X server copies images into through the MIT-SHM extension,
+/// which avoids sending the pixels over the connection
+pub struct SharedImage {
+    seg: shm::Seg,
+    addr: *mut u8,
+    size: usize,
+}
+
+impl SharedImage {
+    /// Creates a segment of `size` bytes and attaches it to the server. Returns `None` if the
+    /// segment cannot be created or the server cannot share memory with us, for example when it
+    /// runs on another machine.
+    pub fn new(conn: &Connection, size: usize) -> Result<Option<SharedImage>> {
+        let present = conn
+            .get_extension_data(shm::id())
+            .is_some_and(|ext| ext.present());
+        if !present || shm::query_version(conn).get_reply().is_err() {
+            return Ok(None);
+        }
+
+        unsafe {
+            let id = libc::shmget(libc::IPC_PRIVATE, size, libc::IPC_CREAT | 0o600);
+            if id == -1 {
+                return Ok(None);
+            }
+
+            let addr = libc::shmat(id, ptr::null(), 0);
+            let seg = conn.generate_id();
+            let attached = addr as isize != -1
+                && shm::attach_checked(conn, seg, id as u32, false)
+                    .request_check()
+                    .is_ok();
+
+            // the segment goes away once both we and the server have detached from it
+            libc::shmctl(id, libc::IPC_RMID, ptr::null_mut());
+
+            if !attached {
+                if addr as isize != -1 {
+                    libc::shmdt(addr);
+                }
+                return Ok(None);
+            }
+
+            Ok(Some(SharedImage {
+                seg,
+                addr: addr as *mut u8,
+                size,
+            }))
+        }
+    }
+
+    /// Reads the pixel values of a rectangle of `drawable`, row by row. Returns `None` if the
+    /// image does not fit into the segment.
+    pub fn read(
+        &self,
+        conn: &Connection,
+        drawable: xproto::Drawable,
+        (x, y, width, height): (i16, i16, u16, u16),
+    ) -> Result<Option<Vec<u32>>> {
+        // at most 32 bits per pixel are used by the depths we support
+        if width as usize * height as usize * 4 > self.size {
+            return Ok(None);
+        }
+
+        let reply = shm::get_image(
+            conn,
+            drawable,
+            x,
+            y,
+            width,
+            height,
+            u32::MAX,
+            xproto::IMAGE_FORMAT_Z_PIXMAP as u8,
+            self.seg,
+            0,
+        )
+        .get_reply()?;
+
+        let layout = ImageLayout::new(conn, reply.depth())?;
+        let data =
+            unsafe { slice::from_raw_parts(self.addr, (reply.size() as usize).min(self.size)) };
+        Ok(Some(layout.read(data, width as usize)))
+    }
+
+    pub fn free(self, conn: &Connection) {
+        shm::detach(conn, self.seg);
+        unsafe {
+            libc::shmdt(self.addr as *const libc::c_void);
+        }
+    }
+}
+
+// Needs an X server that supports MIT-SHM, such as Xvfb, so it only runs with `--ignored`
+#[test]
+#[ignore]
+fn test_shared_image() {
+    let (conn, screen) = Connection::connect(None).expect("no X server to test with");
+    let screen = conn.get_setup().roots().nth(screen as usize).unwrap();
+    let rect = (
+        0,
+        0,
+        screen.width_in_pixels().min(64),
+        screen.height_in_pixels().min(64),
+    );
+
+    let image = SharedImage::new(&conn, 64 * 64 * 4)
+        .unwrap()
+        .expect("the X server does not support shared memory");
+    let shared = image.read(&conn, screen.root(), rect).unwrap().unwrap();
+    let plain = crate::color::window_pixels(&conn, screen.root(), rect).unwrap();
+    assert_eq!(shared, plain);
+
+    assert!(image
+        .read(&conn, screen.root(), (0, 0, 65, 64))
+        .unwrap()
+        .is_none());
+    image.free(&conn);
+}
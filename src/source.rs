@@ -0,0 +1,381 @@
+use anyhow::{anyhow, Result};
+use xcb::base as xbase;
+use xcb::base::Connection;
+use xcb::{composite, xproto};
+
+use crate::color::{self, Color};
+use crate::monitor::{self, Rect};
+use crate::shm::SharedImage;
+use crate::visual::Visual;
+// a named pixmap, the position of a window inside it and the window it belongs to
+type NamedPixmap = (xproto::Pixmap, (i16, i16), xproto::Window);
+
+/// Drawable that colors are read from. Coordinates given to a `Source` are relative to its
+/// origin, which is the top left corner of either the root window or the picked window.
+pub struct Source {
+    drawable: xproto::Drawable,
+    // root window coordinates of the origin
+    origin: (i16, i16),
+    // position of the origin inside `drawable`
+    offset: (i16, i16),
+    width: u16,
+    height: u16,
+    // pixmap named through the Composite extension, owned by us
+    pixmap: Option<xproto::Pixmap>,
+    // visual of the window whose contents `drawable` holds
+    visual: Visual,
+    // pixel values of the whole source captured by `freeze`, row by row
+    frozen: Option<Vec<u32>>,
+    // shared memory that images are read into, if the server supports it
+    shm: Option<SharedImage>,
+    // areas of the root window shown on a monitor, when the contents outside of them are undefined
+    monitors: Option<Vec<Rect>>,
+}
+
+impl Source {
+    pub fn root(conn: &Connection, screen: &xproto::Screen) -> Result<Source> {
+        Ok(Source {
+            drawable: screen.root(),
+            origin: (0, 0),
+            offset: (0, 0),
+            width: screen.width_in_pixels(),
+            height: screen.height_in_pixels(),
+            pixmap: None,
+            visual: Visual::root(screen)?,
+            frozen: None,
+            shm: None,
+            monitors: Some(monitor::monitors(conn, screen)?),
+        })
+    }
+
+    /// Reads colors from the contents of `window`. If the window is redirected by a compositing
+    /// manager, its contents can be read even when other windows cover it. Otherwise the covered
+    /// parts can't be read, and a warning is printed if there are any.
+    pub fn window(
+        conn: &Connection,
+        screen: &xproto::Screen,
+        window: xproto::Window,
+    ) -> Result<Source> {
+        let geometry = xproto::get_geometry(conn, window)
+            .get_reply()
+            .map_err(|_| anyhow!("Could not find window {:#x}", window))?;
+        let origin =
+            xproto::translate_coordinates(conn, window, screen.root(), 0, 0).get_reply()?;
+
+        let (drawable, offset, pixmap, owner) = match name_window_pixmap(conn, screen, window)? {
+            Some((pixmap, offset, owner)) => (pixmap, offset, Some(pixmap), owner),
+            None => {
+                let rect = (
+                    origin.dst_x(),
+                    origin.dst_y(),
+                    geometry.width(),
+                    geometry.height(),
+                );
+                if is_covered(conn, screen, window, rect)? {
+                    eprintln!(
+                        "warning: window {:#x} is covered by other windows and is not redirected \
+                         by a compositing manager, so the covered parts can't be read",
+                        window
+                    );
+                }
+                (window, (0, 0), None, window)
+            }
+        };
+        let attributes = xproto::get_window_attributes(conn, owner).get_reply()?;
+        let visual = Visual::new(screen, attributes.visual(), attributes.colormap())?;
+        // only the off-screen pixmap of a redirected window holds all of its contents
+        let monitors = match pixmap {
+            Some(_) => None,
+            None => Some(monitor::monitors(conn, screen)?),
+        };
+
+        Ok(Source {
+            drawable,
+            origin: (origin.dst_x(), origin.dst_y()),
+            offset,
+            width: geometry.width(),
+            height: geometry.height(),
+            pixmap,
+            visual,
+            frozen: None,
+            shm: None,
+            monitors,
+        })
+    }
+
+    /// Whether the pixels are read from the screen as it is currently shown, so that windows
+    /// drawn on top of the source end up in them
+    pub fn reads_screen(&self) -> bool {
+        self.frozen.is_none() && self.pixmap.is_none()
+    }
+
+    /// Converts root window coordinates into source coordinates
+    pub fn to_local(&self, (x, y): (i16, i16)) -> (i16, i16) {
+        (x - self.origin.0, y - self.origin.1)
+    }
+
+    /// Converts source coordinates into root window coordinates
+    pub fn to_root(&self, (x, y): (i16, i16)) -> (i16, i16) {
+        (x + self.origin.0, y + self.origin.1)
+    }
+
+    /// Intersects a rectangle with the bounds of the source, returning `None` if nothing is left
+    pub fn clamp(&self, (x, y, width, height): Rect) -> Option<Rect> {
+        let x0 = (x as i32).max(0);
+        let y0 = (y as i32).max(0);
+        let x1 = (x as i32 + width as i32).min(self.width as i32);
+        let y1 = (y as i32 + height as i32).min(self.height as i32);
+
+        if x0 >= x1 || y0 >= y1 {
+            return None;
+        }
+
+        Some((x0 as i16, y0 as i16, (x1 - x0) as u16, (y1 - y0) as u16))
+    }
+
+    /// Reads images of up to `size` by `size` pixels through shared memory when the server
+    /// supports it, which is much faster than receiving them over the connection. Larger images
+    /// are still received over the connection.
+    pub fn share_memory(&mut self, conn: &Connection, size: u32) -> Result<()> {
+        // no image is larger than the source, and pixels take at most 32 bits
+        let width = size.min(self.width as u32) as usize;
+        let height = size.min(self.height as u32) as usize;
+        self.shm = SharedImage::new(conn, width * height * 4)?;
+        Ok(())
+    }
+
+    /// Captures the current contents of the source. All later reads are served from this
+    /// snapshot instead of the live contents.
+    pub fn freeze(&mut self, conn: &Connection) -> Result<()> {
+        self.frozen = Some(self.pixels(conn, (0, 0, self.width, self.height))?);
+        Ok(())
+    }
+
+    /// Reads the pixels of a rectangle that lies inside the source. Pixels that are not shown on
+    /// any monitor, such as those in the gaps between monitors of different sizes, have no color.
+    pub fn read(&self, conn: &Connection, rect: Rect) -> Result<Vec<Option<Color>>> {
+        let pixels = match &self.frozen {
+            Some(frozen) => crop(frozen, self.width, rect),
+            None => self.pixels(conn, rect)?,
+        };
+        let mut colors: Vec<_> = self
+            .visual
+            .colors(conn, &pixels)?
+            .into_iter()
+            .map(Some)
+            .collect();
+        self.hide_offscreen(&mut colors, rect);
+        Ok(colors)
+    }
+
+    // Removes the colors of the pixels of `rect` that are not shown on any monitor
+    fn hide_offscreen(&self, colors: &mut [Option<Color>], (x, y, width, _): Rect) {
+        let monitors = match &self.monitors {
+            Some(monitors) => monitors,
+            None => return,
+        };
+
+        for (i, color) in colors.iter_mut().enumerate() {
+            let point = (
+                x + (i % width as usize) as i16,
+                y + (i / width as usize) as i16,
+            );
+            if monitor::containing(monitors, self.to_root(point)).is_none() {
+                *color = None;
+            }
+        }
+    }
+
+    // Reads the current pixel values of a rectangle that lies inside the source
+    fn pixels(&self, conn: &Connection, (x, y, width, height): Rect) -> Result<Vec<u32>> {
+        let rect = (x + self.offset.0, y + self.offset.1, width, height);
+        if let Some(shm) = &self.shm {
+            if let Some(pixels) = shm.read(conn, self.drawable, rect)? {
+                return Ok(pixels);
+            }
+        }
+        color::window_pixels(conn, self.drawable, rect)
+    }
+
+    pub fn free(self, conn: &Connection) {
+        if let Some(pixmap) = self.pixmap {
+            xproto::free_pixmap(conn, pixmap);
+        }
+        if let Some(shm) = self.shm {
+            shm.free(conn);
+        }
+    }
+}
+
+// Copies a rectangle out of the pixels of an image that is `width` pixels wide
+fn crop(pixels: &[u32], width: u16, (x, y, rect_width, rect_height): Rect) -> Vec<u32> {
+    (y as usize..y as usize + rect_height as usize)
+        .flat_map(|row| {
+            let start = row * width as usize + x as usize;
+            pixels[start..start + rect_width as usize].iter().copied()
+        })
+        .collect()
+}
+
+// Names the off-screen pixmap holding the contents of `window` or of the top-level window that
+// contains it. Returns the pixmap, the position of `window` inside it and the window the pixmap
+// belongs to, or `None` if the window is not redirected.
+fn name_window_pixmap(
+    conn: &Connection,
+    screen: &xproto::Screen,
+    window: xproto::Window,
+) -> Result<Option<NamedPixmap>> {
+    let present = conn
+        .get_extension_data(composite::id())
+        .is_some_and(|ext| ext.present());
+    if !present {
+        return Ok(None);
+    }
+
+    // NameWindowPixmap was added in version 0.2
+    let version = composite::query_version(conn, 0, 2).get_reply()?;
+    if version.major_version() == 0 && version.minor_version() < 2 {
+        return Ok(None);
+    }
+
+    // the id is only used once a request succeeds, so the same one is tried for every window
+    let pixmap = conn.generate_id();
+    let mut candidate = window;
+    loop {
+        if composite::name_window_pixmap_checked(conn, candidate, pixmap)
+            .request_check()
+            .is_ok()
+        {
+            // the pixmap includes the border of the window
+            let border = xproto::get_geometry(conn, candidate)
+                .get_reply()?
+                .border_width() as i16;
+            let position =
+                xproto::translate_coordinates(conn, window, candidate, 0, 0).get_reply()?;
+            return Ok(Some((
+                pixmap,
+                (position.dst_x() + border, position.dst_y() + border),
+                candidate,
+            )));
+        }
+
+        let parent = xproto::query_tree(conn, candidate).get_reply()?.parent();
+        if parent == screen.root() || parent == xbase::NONE {
+            return Ok(None);
+        }
+        candidate = parent;
+    }
+}
+
+// Whether any window stacked above the top-level window containing `window` overlaps `rect`,
+// given in root window coordinates
+fn is_covered(
+    conn: &Connection,
+    screen: &xproto::Screen,
+    window: xproto::Window,
+    rect: Rect,
+) -> Result<bool> {
+    let mut top_level = window;
+    loop {
+        let parent = xproto::query_tree(conn, top_level).get_reply()?.parent();
+        if parent == screen.root() || parent == xbase::NONE {
+            break;
+        }
+        top_level = parent;
+    }
+
+    // children are listed from the bottom of the stack to the top
+    let tree = xproto::query_tree(conn, screen.root()).get_reply()?;
+    let above = tree
+        .children()
+        .iter()
+        .skip_while(|&&child| child != top_level)
+        .skip(1);
+    for &sibling in above {
+        let attributes = xproto::get_window_attributes(conn, sibling).get_reply()?;
+        let shown = attributes.map_state() == xproto::MAP_STATE_VIEWABLE as u8
+            && attributes.class() == xproto::WINDOW_CLASS_INPUT_OUTPUT as u16;
+        if !shown {
+            continue;
+        }
+        let geometry = xproto::get_geometry(conn, sibling).get_reply()?;
+        let border = geometry.border_width() * 2;
+        let sibling_rect = (
+            geometry.x(),
+            geometry.y(),
+            geometry.width() + border,
+            geometry.height() + border,
+        );
+        if monitor::overlaps(rect, sibling_rect) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[test]
+fn test_clamp() {
+    let source = Source {
+        drawable: 0,
+        origin: (100, 50),
+        offset: (0, 0),
+        width: 20,
+        height: 10,
+        pixmap: None,
+        visual: Visual::true_color(0, 0xff_0000, 0x00_ff00, 0x00_00ff),
+        frozen: None,
+        shm: None,
+        monitors: None,
+    };
+
+    assert_eq!(source.clamp((0, 0, 20, 10)), Some((0, 0, 20, 10)));
+    assert_eq!(source.clamp((-5, -5, 10, 10)), Some((0, 0, 5, 5)));
+    assert_eq!(source.clamp((15, 5, 10, 10)), Some((15, 5, 5, 5)));
+    assert_eq!(source.clamp((20, 0, 5, 5)), None);
+    assert_eq!(source.clamp((-10, 0, 10, 5)), None);
+
+    assert_eq!(source.to_local((110, 55)), (10, 5));
+    assert_eq!(source.to_root((10, 5)), (110, 55));
+}
+
+#[test]
+fn test_crop() {
+    let pixels: Vec<u32> = (0..12).collect();
+
+    assert_eq!(crop(&pixels, 4, (0, 0, 4, 3)), pixels);
+    assert_eq!(crop(&pixels, 4, (1, 1, 2, 2)), [5, 6, 9, 10]);
+    assert_eq!(crop(&pixels, 4, (3, 0, 1, 3)), [3, 7, 11]);
+}
+
+#[test]
+fn test_hide_offscreen() {
+    // a window at (100, 50) that reaches past the bottom of a monitor
+    let source = Source {
+        drawable: 0,
+        origin: (100, 50),
+        offset: (0, 0),
+        width: 4,
+        height: 4,
+        pixmap: None,
+        visual: Visual::true_color(0, 0xff_0000, 0x00_ff00, 0x00_00ff),
+        frozen: None,
+        shm: None,
+        monitors: Some(vec![(0, 0, 102, 52), (102, 0, 100, 100)]),
+    };
+    let white = Color::new(0xffff, 0xffff, 0xffff, 0xffff, 8);
+
+    let mut colors = vec![Some(white); 16];
+    source.hide_offscreen(&mut colors, (0, 0, 4, 4));
+    let shown: Vec<bool> = colors.iter().map(|c| c.is_some()).collect();
+    #[rustfmt::skip]
+    assert_eq!(shown, [
+        true, true, true, true,
+        true, true, true, true,
+        false, false, true, true,
+        false, false, true, true,
+    ]);
+
+    let mut colors = vec![Some(white); 2];
+    source.hide_offscreen(&mut colors, (1, 2, 2, 1));
+    assert!(colors[0].is_none() && colors[1] == Some(white));
+}
@@ -0,0 +1,303 @@
+use std::f64::consts::PI;
+
+use crate::color::Color;
+
+// CIELAB constants as defined by the CIE, in their exact rational form
+const LAB_EPSILON: f64 = 216.0 / 24389.0;
+const LAB_KAPPA: f64 = 24389.0 / 27.0;
+
+// Reference white of CIELAB, which is specified relative to D50. The matrices below are derived
+// from the chromaticities of the white points and primaries the same way as in CSS Color Module
+// Level 4, so results match browsers.
+const D50_WHITE: [f64; 3] = [0.3457 / 0.3585, 1.0, (1.0 - 0.3457 - 0.3585) / 0.3585];
+
+// Converts linear sRGB into CIE XYZ relative to D65
+const SRGB_TO_XYZ: [[f64; 3]; 3] = [
+    [
+        506_752.0 / 1_228_815.0,
+        87_881.0 / 245_763.0,
+        12_673.0 / 70_218.0,
+    ],
+    [
+        87_098.0 / 409_605.0,
+        175_762.0 / 245_763.0,
+        12_673.0 / 175_545.0,
+    ],
+    [
+        7_918.0 / 409_605.0,
+        87_881.0 / 737_289.0,
+        1_001_167.0 / 1_053_270.0,
+    ],
+];
+
+// Bradford chromatic adaptation from D65 to D50
+const D65_TO_D50: [[f64; 3]; 3] = [
+    [
+        1.047_929_792_544_997,
+        0.022_946_870_601_609_652,
+        -0.050_192_266_289_205_24,
+    ],
+    [
+        0.029_627_808_770_055_99,
+        0.990_434_426_753_879_9,
+        -0.017_073_799_063_418_826,
+    ],
+    [
+        -0.009_243_040_646_204_504,
+        0.015_055_191_490_298_152,
+        0.751_874_281_428_137_1,
+    ],
+];
+
+// Converts linear sRGB into the cone responses of OKLab
+const SRGB_TO_LMS: [[f64; 3]; 3] = [
+    [0.412_221_470_8, 0.536_332_536_3, 0.051_445_992_9],
+    [0.211_903_498_2, 0.680_699_545_1, 0.107_396_956_6],
+    [0.088_302_461_9, 0.281_718_837_6, 0.629_978_700_5],
+];
+
+// Converts the compressed cone responses into OKLab
+const LMS_TO_OKLAB: [[f64; 3]; 3] = [
+    [0.210_454_255_3, 0.793_617_785_0, -0.004_072_046_8],
+    [1.977_998_495_1, -2.428_592_205_0, 0.450_593_709_9],
+    [0.025_904_037_1, 0.782_771_766_2, -0.808_675_766_0],
+];
+
+// Chroma below which a color is treated as gray, whose hue is 0
+const ACHROMATIC: f64 = 1e-4;
+
+fn multiply(matrix: &[[f64; 3]; 3], vector: [f64; 3]) -> [f64; 3] {
+    matrix.map(|row| row[0] * vector[0] + row[1] * vector[1] + row[2] * vector[2])
+}
+
+/// Converts an sRGB encoded value from 0 to 1 into linear light
+pub fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts a linear light value from 0 to 1 into sRGB encoding
+pub fn linear_to_srgb(value: f64) -> f64 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The red, green and blue channels of a color in linear light, from 0 to 1
+pub fn linear_rgb(color: Color) -> [f64; 3] {
+    [color.r, color.g, color.b].map(|value| srgb_to_linear(f64::from(value) / 65535.0))
+}
+
+/// CIE XYZ coordinates relative to the D65 white point, where white has a Y of 1
+pub fn xyz(color: Color) -> [f64; 3] {
+    multiply(&SRGB_TO_XYZ, linear_rgb(color))
+}
+
+/// CIELAB coordinates relative to D50: lightness from 0 to 100 and the a and b axes
+pub fn lab(color: Color) -> [f64; 3] {
+    let xyz = multiply(&D65_TO_D50, xyz(color));
+    let [fx, fy, fz] = [0, 1, 2].map(|i| {
+        let t = xyz[i] / D50_WHITE[i];
+        if t > LAB_EPSILON {
+            t.cbrt()
+        } else {
+            (LAB_KAPPA * t + 16.0) / 116.0
+        }
+    });
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// Cylindrical form of CIELAB: lightness, chroma and hue in degrees
+pub fn lch(color: Color) -> [f64; 3] {
+    polar(lab(color))
+}
+
+/// OKLab coordinates: lightness from 0 to 1 and the a and b axes
+pub fn oklab(color: Color) -> [f64; 3] {
+    let lms = multiply(&SRGB_TO_LMS, linear_rgb(color)).map(f64::cbrt);
+    multiply(&LMS_TO_OKLAB, lms)
+}
+
+/// Cylindrical form of OKLab: lightness, chroma and hue in degrees
+pub fn oklch(color: Color) -> [f64; 3] {
+    polar(oklab(color))
+}
+
+// Converts lightness and two opposing color axes into lightness, chroma and hue
+fn polar([lightness, a, b]: [f64; 3]) -> [f64; 3] {
+    let chroma = a.hypot(b);
+    if chroma < ACHROMATIC {
+        return [lightness, 0.0, 0.0];
+    }
+    let hue = (b.atan2(a) * 180.0 / PI).rem_euclid(360.0);
+    [lightness, chroma, hue]
+}
+
+/// The CIEDE2000 color difference between two CIELAB colors, where a difference of about 1 is
+/// just noticeable
+pub fn delta_e([l1, a1, b1]: [f64; 3], [l2, a2, b2]: [f64; 3]) -> f64 {
+    // the a axis is stretched for colors of low chroma
+    let chroma_mean = (a1.hypot(b1) + a2.hypot(b2)) / 2.0;
+    let g = 0.5 * (1.0 - chroma_ratio(chroma_mean));
+    let (a1, a2) = (a1 * (1.0 + g), a2 * (1.0 + g));
+    let (c1, c2) = (a1.hypot(b1), a2.hypot(b2));
+    let hue = |a: f64, b: f64| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            b.atan2(a).to_degrees().rem_euclid(360.0)
+        }
+    };
+    let (h1, h2) = (hue(a1, b1), hue(a2, b2));
+
+    // differences, where the hue difference takes the shorter way around
+    let delta_l = l2 - l1;
+    let delta_c = c2 - c1;
+    let delta_h = match h2 - h1 {
+        _ if c1 * c2 == 0.0 => 0.0,
+        d if d > 180.0 => d - 360.0,
+        d if d < -180.0 => d + 360.0,
+        d => d,
+    };
+    let delta_h = 2.0 * (c1 * c2).sqrt() * (delta_h / 2.0).to_radians().sin();
+
+    // means, where the mean hue lies between the hues along the shorter way
+    let l_mean = (l1 + l2) / 2.0;
+    let c_mean = (c1 + c2) / 2.0;
+    let h_mean = if c1 * c2 == 0.0 {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    } else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    } else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+
+    let cos = |degrees: f64| degrees.to_radians().cos();
+    let t =
+        1.0 - 0.17 * cos(h_mean - 30.0) + 0.24 * cos(2.0 * h_mean) + 0.32 * cos(3.0 * h_mean + 6.0)
+            - 0.20 * cos(4.0 * h_mean - 63.0);
+    let l_offset = (l_mean - 50.0).powi(2);
+    let weight_l = 1.0 + 0.015 * l_offset / (20.0 + l_offset).sqrt();
+    let weight_c = 1.0 + 0.045 * c_mean;
+    let weight_h = 1.0 + 0.015 * c_mean * t;
+    // rotation that corrects the blue region
+    let theta = 30.0 * (-((h_mean - 275.0) / 25.0).powi(2)).exp();
+    let rotation = -2.0 * chroma_ratio(c_mean) * (2.0 * theta).to_radians().sin();
+
+    let (l, c, h) = (delta_l / weight_l, delta_c / weight_c, delta_h / weight_h);
+    (l * l + c * c + h * h + rotation * c * h).sqrt()
+}
+
+// The factor of CIEDE2000 that approaches 1 for colors of high chroma
+fn chroma_ratio(chroma: f64) -> f64 {
+    let c7 = chroma.powi(7);
+    (c7 / (c7 + 25f64.powi(7))).sqrt()
+}
+
+#[cfg(test)]
+fn assert_close(actual: [f64; 3], expected: [f64; 3], tolerance: f64) {
+    let close = actual
+        .iter()
+        .zip(expected.iter())
+        .all(|(a, e)| (a - e).abs() <= tolerance);
+    assert!(close, "{:?} is not close to {:?}", actual, expected);
+}
+
+#[test]
+fn test_transfer_function() {
+    assert_eq!(srgb_to_linear(0.0), 0.0);
+    assert!((srgb_to_linear(1.0) - 1.0).abs() < 1e-12);
+    assert!((srgb_to_linear(0.5) - 0.214_041).abs() < 1e-6);
+    for i in 0..=100 {
+        let value = i as f64 / 100.0;
+        assert!((linear_to_srgb(srgb_to_linear(value)) - value).abs() < 1e-9);
+    }
+}
+
+// Reference values for the sRGB primaries and white from the sample conversions of CSS Color
+// Module Level 4, and from Björn Ottosson's OKLab post for the OKLab values
+#[test]
+fn test_reference_values() {
+    use crate::color::ARGB;
+    let color = |r, g, b| Color::from(ARGB::new(0xff, r, g, b));
+    let (red, lime, blue) = (color(0xff, 0, 0), color(0, 0xff, 0), color(0, 0, 0xff));
+    let white = Color::from(ARGB::WHITE);
+
+    assert_close(xyz(red), [0.412_391, 0.212_639, 0.019_331], 1e-6);
+    assert_close(xyz(white), [0.950_456, 1.0, 1.089_058], 1e-6);
+
+    assert_close(lab(red), [54.290_5, 80.804_9, 69.891_0], 0.01);
+    assert_close(lab(lime), [87.818_5, -79.271_1, 80.994_6], 0.01);
+    assert_close(lab(blue), [29.568_3, 68.287_4, -112.029_4], 0.01);
+    assert_close(lab(white), [100.0, 0.0, 0.0], 0.01);
+
+    assert_close(lch(red), [54.290_5, 106.837_2, 40.857_7], 0.01);
+    assert_close(lch(blue), [29.568_3, 131.201_5, 301.364_9], 0.01);
+
+    assert_close(oklab(red), [0.627_955, 0.224_863, 0.125_846], 1e-4);
+    assert_close(oklab(lime), [0.866_440, -0.233_888, 0.179_498], 1e-4);
+    assert_close(oklab(blue), [0.452_014, -0.032_457, -0.311_528], 1e-4);
+    assert_close(oklab(white), [1.0, 0.0, 0.0], 1e-4);
+
+    assert_close(oklch(red), [0.627_955, 0.257_683, 29.233_885], 1e-3);
+    assert_close(oklch(blue), [0.452_014, 0.313_214, 264.052_021], 1e-3);
+
+    // grays have no hue
+    let gray = color(0x80, 0x80, 0x80);
+    assert_eq!(lch(gray)[1..], [0.0, 0.0]);
+    assert_eq!(oklch(gray)[1..], [0.0, 0.0]);
+}
+
+// Test data of Sharma, Wu and Dalal, "The CIEDE2000 Color-Difference Formula: Implementation
+// Notes, Supplementary Test Data, and Mathematical Observations"
+#[test]
+fn test_delta_e() {
+    let pairs = [
+        ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+        ([50.0, 0.0, 0.0], [50.0, -1.0, 2.0], 2.3669),
+        ([50.0, 2.5, 0.0], [73.0, 25.0, -18.0], 27.1492),
+        (
+            [60.2574, -34.0099, 36.2677],
+            [60.4626, -34.1751, 39.4387],
+            1.2644,
+        ),
+        (
+            [63.0109, -31.0961, -5.8663],
+            [62.8187, -29.7946, -4.0864],
+            1.2630,
+        ),
+        (
+            [22.7233, 20.0904, -46.6940],
+            [23.0331, 14.9730, -42.5619],
+            2.0373,
+        ),
+        (
+            [2.0776, 0.0795, -1.1350],
+            [0.9033, -0.0636, -0.5514],
+            0.9082,
+        ),
+    ];
+    for (lab1, lab2, expected) in pairs {
+        assert!(
+            (delta_e(lab1, lab2) - expected).abs() < 1e-4,
+            "{:?} {:?}",
+            lab1,
+            lab2
+        );
+        // the difference is symmetric
+        assert!(
+            (delta_e(lab2, lab1) - expected).abs() < 1e-4,
+            "{:?} {:?}",
+            lab2,
+            lab1
+        );
+    }
+    assert_eq!(delta_e([50.0, 10.0, -10.0], [50.0, 10.0, -10.0]), 0.0);
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 /// A simple trait which makes it easy to ensure a given type is odd.
 pub trait EnsureOdd {
     fn ensure_odd(self) -> Self;
@@ -22,3 +24,95 @@ impl_ensure_odd!(u16);
 impl_ensure_odd!(u32);
 impl_ensure_odd!(isize);
 impl_ensure_odd!(usize);
+
+/// Parses a point written as `X,Y`
+pub fn parse_point(s: &str) -> Option<(i16, i16)> {
+    let (x, y) = s.split_once(',')?;
+    Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+/// Parses a decimal or `0x` prefixed hexadecimal X resource id
+pub fn parse_id(s: &str) -> Option<u32> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Parses a duration written as milliseconds or seconds, such as `500ms` or `1.5s`. A number
+/// without a unit is taken as milliseconds.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    let (number, seconds) = if let Some(ms) = s.strip_suffix("ms") {
+        (ms, 0.001)
+    } else if let Some(secs) = s.strip_suffix('s') {
+        (secs, 1.0)
+    } else {
+        (s, 0.001)
+    };
+
+    let number: f64 = number.trim().parse().ok()?;
+    // rejects negative and non-finite numbers as well as durations too long to represent
+    Duration::try_from_secs_f64(number * seconds).ok()
+}
+
+/// Quotes a string as a JSON string literal
+pub fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[test]
+fn test_parse_point() {
+    assert_eq!(parse_point("10,20"), Some((10, 20)));
+    assert_eq!(parse_point("0, 5"), Some((0, 5)));
+    assert_eq!(parse_point("-1,2"), Some((-1, 2)));
+    assert_eq!(parse_point("10"), None);
+    assert_eq!(parse_point("10,"), None);
+    assert_eq!(parse_point("a,b"), None);
+    assert_eq!(parse_point("1,2,3"), None);
+}
+
+#[test]
+fn test_parse_id() {
+    assert_eq!(parse_id("12345"), Some(12345));
+    assert_eq!(parse_id("0x1a00003"), Some(0x1a00003));
+    assert_eq!(parse_id("0XFF"), Some(0xff));
+    assert_eq!(parse_id("0x"), None);
+    assert_eq!(parse_id("ff"), None);
+    assert_eq!(parse_id(""), None);
+}
+
+#[test]
+fn test_parse_duration() {
+    assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+    assert_eq!(parse_duration("250"), Some(Duration::from_millis(250)));
+    assert_eq!(parse_duration("2s"), Some(Duration::from_secs(2)));
+    assert_eq!(parse_duration("1.5s"), Some(Duration::from_millis(1500)));
+    assert_eq!(parse_duration("0"), Some(Duration::ZERO));
+    assert_eq!(parse_duration("-1s"), None);
+    assert_eq!(parse_duration("ms"), None);
+    assert_eq!(parse_duration("1m"), None);
+    assert_eq!(parse_duration("inf"), None);
+    assert_eq!(parse_duration("1e20s"), None);
+}
+
+#[test]
+fn test_json_string() {
+    assert_eq!(json_string("#ff00ff"), "\"#ff00ff\"");
+    assert_eq!(json_string(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+    assert_eq!(json_string("a\nb\u{1}"), r#""a\nb\u0001""#);
+}
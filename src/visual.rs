@@ -0,0 +1,238 @@
+use anyhow::{anyhow, Result};
+use xcb::base::Connection;
+use xcb::xproto;
+
+use crate::color::Color;
+
+/// Describes how the pixel values of a drawable translate into colors
+pub struct Visual {
+    class: u8,
+    // bits of the pixel values that hold no color, which is the alpha channel of 32-bit visuals
+    alpha_mask: u32,
+    red_mask: u32,
+    green_mask: u32,
+    blue_mask: u32,
+    // significant bits per channel
+    bits: u8,
+    // used to look up the colors of visuals other than TrueColor
+    colormap: xproto::Colormap,
+}
+
+impl Visual {
+    /// Looks up `visual` among the visuals supported by `screen`
+    pub fn new(
+        screen: &xproto::Screen,
+        visual: xproto::Visualid,
+        colormap: xproto::Colormap,
+    ) -> Result<Visual> {
+        screen
+            .allowed_depths()
+            .flat_map(|depth| {
+                let depth_mask = u32::MAX >> (32 - u32::from(depth.depth()).clamp(1, 32));
+                depth.visuals().map(move |visual| (depth_mask, visual))
+            })
+            .find(|(_, other)| other.visual_id() == visual)
+            .map(|(depth_mask, other)| Visual {
+                class: other.class(),
+                alpha_mask: depth_mask
+                    & !(other.red_mask() | other.green_mask() | other.blue_mask()),
+                red_mask: other.red_mask(),
+                green_mask: other.green_mask(),
+                blue_mask: other.blue_mask(),
+                bits: other.bits_per_rgb_value(),
+                colormap,
+            })
+            .ok_or_else(|| anyhow!("Could not find visual {:#x}", visual))
+    }
+
+    /// The visual of the root window of `screen`
+    pub fn root(screen: &xproto::Screen) -> Result<Visual> {
+        Visual::new(screen, screen.root_visual(), screen.default_colormap())
+    }
+
+    /// Converts pixel values into colors
+    pub fn colors(&self, conn: &Connection, pixels: &[u32]) -> Result<Vec<Color>> {
+        if self.class == xproto::VISUAL_CLASS_TRUE_COLOR as u8 {
+            return Ok(pixels.iter().map(|&pixel| self.split(pixel)).collect());
+        }
+
+        // all other visuals go through the colormap, so look up every distinct value once
+        let mut values = pixels.to_vec();
+        values.sort_unstable();
+        values.dedup();
+        let reply = xproto::query_colors(conn, self.colormap, &values).get_reply()?;
+        let colors: Vec<Color> = reply
+            .colors()
+            .map(|rgb| Color::new(0xffff, rgb.red(), rgb.green(), rgb.blue(), self.bits))
+            .collect();
+
+        Ok(pixels
+            .iter()
+            .map(|pixel| colors[values.binary_search(pixel).unwrap()])
+            .collect())
+    }
+
+    // Splits a pixel value into its channels using the color masks
+    fn split(&self, pixel: u32) -> Color {
+        let (r, g, b) = (
+            channel(pixel, self.red_mask),
+            channel(pixel, self.green_mask),
+            channel(pixel, self.blue_mask),
+        );
+        if self.alpha_mask == 0 || self.class != xproto::VISUAL_CLASS_TRUE_COLOR as u8 {
+            return Color::new(0xffff, r, g, b, self.bits);
+        }
+
+        // the colors of ARGB windows are premultiplied by their alpha, as in the Render extension
+        let a = channel(pixel, self.alpha_mask);
+        let unmultiply = |value: u16| match a {
+            0 => 0,
+            a => ((u32::from(value) * 0xffff + u32::from(a) / 2) / u32::from(a)).min(0xffff) as u16,
+        };
+        Color::new(a, unmultiply(r), unmultiply(g), unmultiply(b), self.bits)
+    }
+}
+
+#[cfg(test)]
+impl Visual {
+    pub fn true_color(alpha_mask: u32, red_mask: u32, green_mask: u32, blue_mask: u32) -> Visual {
+        Visual {
+            class: xproto::VISUAL_CLASS_TRUE_COLOR as u8,
+            alpha_mask,
+            red_mask,
+            green_mask,
+            blue_mask,
+            bits: red_mask.count_ones() as u8,
+            colormap: 0,
+        }
+    }
+}
+
+// Extracts the channel covered by `mask` from a pixel value and scales it to 16 bits
+fn channel(pixel: u32, mask: u32) -> u16 {
+    if mask == 0 {
+        return 0;
+    }
+
+    let shift = mask.trailing_zeros();
+    Color::expand((pixel & mask) >> shift, mask.count_ones() as u8)
+}
+
+/// How the server packs pixel values into the data of Z pixmap images
+pub struct ImageLayout {
+    bits_per_pixel: usize,
+    scanline_pad: usize,
+    lsb_first: bool,
+}
+
+impl ImageLayout {
+    /// The layout of images of `depth`
+    pub fn new(conn: &Connection, depth: u8) -> Result<ImageLayout> {
+        let setup = conn.get_setup();
+        let format = setup
+            .pixmap_formats()
+            .find(|format| format.depth() == depth)
+            .ok_or_else(|| anyhow!("Unsupported color depth {}", depth))?;
+
+        // pixels smaller than a byte are only used by monochrome and 4-bit displays
+        if format.bits_per_pixel() % 8 != 0 {
+            return Err(anyhow!("Unsupported color depth {}", depth));
+        }
+
+        Ok(ImageLayout {
+            bits_per_pixel: format.bits_per_pixel() as usize,
+            scanline_pad: format.scanline_pad() as usize,
+            lsb_first: setup.image_byte_order() == xproto::IMAGE_ORDER_LSB_FIRST as u8,
+        })
+    }
+
+    /// Reads the pixel values of an image that is `width` pixels wide, row by row
+    pub fn read(&self, data: &[u8], width: usize) -> Vec<u32> {
+        let bytes = self.bits_per_pixel / 8;
+        // every row is padded to a multiple of the scanline pad
+        let row_bits = width * self.bits_per_pixel;
+        let stride = row_bits.div_ceil(self.scanline_pad) * self.scanline_pad / 8;
+
+        data.chunks(stride)
+            .flat_map(|row| row[..width * bytes].chunks(bytes))
+            .map(|pixel| {
+                let fold = |value, &byte| (value << 8) | byte as u32;
+                if self.lsb_first {
+                    pixel.iter().rev().fold(0, fold)
+                } else {
+                    pixel.iter().fold(0, fold)
+                }
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_channel() {
+    // 5-6-5
+    assert_eq!(channel(0xf800, 0xf800), 0xffff);
+    assert_eq!(channel(0x07e0, 0x07e0), 0xffff);
+    assert_eq!(channel(0x0010, 0x001f), 0x8421);
+    assert_eq!(channel(0x0000, 0x001f), 0x0000);
+    // 10 bits per channel
+    assert_eq!(channel(0x3ff0_0000, 0x3ff0_0000), 0xffff);
+    assert_eq!(channel(0x2000_0000, 0x3ff0_0000), 0x8020);
+    assert_eq!(channel(0x1234, 0), 0);
+}
+
+#[test]
+fn test_split() {
+    use crate::color::ARGB;
+    let visual = Visual::true_color;
+
+    let rgb888 = visual(0, 0xff_0000, 0x00_ff00, 0x00_00ff);
+    assert!(ARGB::from(rgb888.split(0x12_3456)) == ARGB::new(0xff, 0x12, 0x34, 0x56));
+
+    let rgb555 = visual(0, 0x7c00, 0x03e0, 0x001f);
+    assert!(ARGB::from(rgb555.split(0x7fff)) == ARGB::WHITE);
+    assert!(ARGB::from(rgb555.split(0x7c00)) == ARGB::new(0xff, 0xff, 0, 0));
+
+    let bgr101010 = visual(0, 0x0000_03ff, 0x000f_fc00, 0x3ff0_0000);
+    let blue = bgr101010.split(0x2000_0000);
+    assert_eq!(
+        (blue.r, Color::reduce(blue.b, 10), blue.bits),
+        (0, 0x200, 10)
+    );
+
+    // premultiplied half transparent red, and a fully transparent pixel
+    let argb8888 = visual(0xff00_0000, 0xff_0000, 0x00_ff00, 0x00_00ff);
+    assert!(ARGB::from(argb8888.split(0x8080_0000)) == ARGB::new(0x80, 0xff, 0, 0));
+    assert!(ARGB::from(argb8888.split(0x4020_1000)) == ARGB::new(0x40, 0x80, 0x40, 0));
+    assert!(ARGB::from(argb8888.split(0)) == ARGB::TRANSPARENT);
+    assert!(ARGB::from(argb8888.split(0xff12_3456)) == ARGB::new(0xff, 0x12, 0x34, 0x56));
+}
+
+#[test]
+fn test_read() {
+    let layout = |bits_per_pixel, scanline_pad, lsb_first| ImageLayout {
+        bits_per_pixel,
+        scanline_pad,
+        lsb_first,
+    };
+
+    // 16 bits per pixel, rows padded to 32 bits
+    let data = [
+        0x34, 0x12, 0x78, 0x56, 0x00, 0x00, 0x00, 0x00, 0xcd, 0xab, 0x01, 0xef, 0x00, 0x00, 0x00,
+        0x00,
+    ];
+    assert_eq!(
+        layout(16, 32, true).read(&data, 3),
+        [0x1234, 0x5678, 0x0000, 0xabcd, 0xef01, 0x0000]
+    );
+    assert_eq!(
+        layout(16, 32, false).read(&data, 3),
+        [0x3412, 0x7856, 0x0000, 0xcdab, 0x01ef, 0x0000]
+    );
+
+    // packed 24 bits per pixel
+    let data = [0x56, 0x34, 0x12, 0xcc, 0xbb, 0xaa, 0x00, 0x00];
+    assert_eq!(layout(24, 32, true).read(&data, 2), [0x12_3456, 0xaa_bbcc]);
+
+    let data = [0x56, 0x34, 0x12, 0x00, 0xcc, 0xbb, 0xaa, 0x00];
+    assert_eq!(layout(32, 32, true).read(&data, 2), [0x12_3456, 0xaa_bbcc]);
+}